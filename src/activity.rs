@@ -0,0 +1,154 @@
+use crate::chat::{Message, scan};
+use crate::fulltext::tokenize;
+use rocket::serde::Serialize;
+use schemars::JsonSchema;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+const TOP_KEYWORDS: usize = 5;
+const MAX_BINS: usize = 1 << 20;
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Activity {
+    pub interval_ms: u64,
+    pub messages: u64,
+    pub mean: f64,
+    pub stddev: f64,
+    pub histogram: Vec<u32>,
+    pub highlights: Vec<Highlight>,
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Highlight {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub peak_ms: u64,
+    pub messages: u32,
+    pub score: f64,
+    pub keywords: Vec<Keyword>,
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Keyword {
+    pub token: String,
+    pub count: u32,
+}
+
+#[derive(Default)]
+struct Bin {
+    count: u32,
+    tokens: HashMap<String, u32>,
+}
+
+// Emotes arrive as `:shortcut:` runs; keep them whole so they rank as one keyword.
+fn keywords(message: &Message) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for word in message.text.split_whitespace() {
+        if word.len() > 2 && word.starts_with(':') && word.ends_with(':') {
+            tokens.push(word.to_owned());
+        } else {
+            tokens.extend(tokenize(word).into_iter().map(|(_, t)| t));
+        }
+    }
+    tokens.sort();
+    tokens.dedup();
+    tokens
+}
+
+// Bins messages by playback offset and flags runs of bins whose rate is more than
+// `threshold` standard deviations above the mean as highlight candidates.
+pub fn analyze(
+    path: &Path,
+    interval_ms: u64,
+    threshold: f64,
+    limit: usize,
+) -> io::Result<Activity> {
+    let mut bins: Vec<Bin> = Vec::new();
+    scan(path, |message| {
+        let i = (message.offset_ms / interval_ms) as usize;
+        if i >= MAX_BINS {
+            return;
+        }
+        if bins.len() <= i {
+            bins.resize_with(i + 1, Bin::default);
+        }
+        let bin = &mut bins[i];
+        bin.count += 1;
+        for token in keywords(&message) {
+            *bin.tokens.entry(token).or_default() += 1;
+        }
+    })?;
+
+    let histogram: Vec<u32> = bins.iter().map(|b| b.count).collect();
+    let messages = histogram.iter().map(|&c| c as u64).sum();
+    let n = histogram.len().max(1) as f64;
+    let mean = messages as f64 / n;
+    let variance = histogram
+        .iter()
+        .map(|&c| (c as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n;
+    let stddev = variance.sqrt();
+    let score = |count: u32| {
+        if stddev > 0.0 {
+            (count as f64 - mean) / stddev
+        } else {
+            0.0
+        }
+    };
+
+    let hot = |bin: &Bin| score(bin.count) >= threshold;
+
+    let mut highlights = Vec::new();
+    let mut i = 0;
+    while i < bins.len() {
+        if !hot(&bins[i]) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < bins.len() && hot(&bins[i]) {
+            i += 1;
+        }
+        let window = &bins[start..i];
+        let peak = (start..i).max_by_key(|&j| bins[j].count).unwrap_or(start);
+        let mut tokens: HashMap<&str, u32> = HashMap::new();
+        for bin in window {
+            for (token, count) in &bin.tokens {
+                *tokens.entry(token).or_default() += count;
+            }
+        }
+        let mut keywords: Vec<_> = tokens
+            .into_iter()
+            .map(|(token, count)| Keyword {
+                token: token.to_owned(),
+                count,
+            })
+            .collect();
+        keywords.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.token.cmp(&b.token)));
+        keywords.truncate(TOP_KEYWORDS);
+        highlights.push(Highlight {
+            start_ms: start as u64 * interval_ms,
+            end_ms: i as u64 * interval_ms,
+            peak_ms: peak as u64 * interval_ms,
+            messages: window.iter().map(|b| b.count).sum(),
+            score: score(bins[peak].count),
+            keywords,
+        });
+    }
+    highlights.sort_by(|a, b| b.score.total_cmp(&a.score));
+    highlights.truncate(limit);
+
+    Ok(Activity {
+        interval_ms,
+        messages,
+        mean,
+        stddev,
+        histogram,
+        highlights,
+    })
+}
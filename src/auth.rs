@@ -0,0 +1,34 @@
+use crate::config::ArchiveConfig;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+
+pub struct Auth;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Auth {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(config) = req.rocket().state::<ArchiveConfig>() else {
+            return Outcome::Error((Status::InternalServerError, ()));
+        };
+        let token = req
+            .headers()
+            .get_one("Authorization")
+            .and_then(|h| h.strip_prefix("Bearer "));
+        match token {
+            Some(token) if config.tokens.iter().any(|t| constant_time_eq(t, token)) => {
+                Outcome::Success(Auth)
+            }
+            _ => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
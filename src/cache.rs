@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+type Stamp = (SystemTime, u64);
+type Slot<T> = Arc<Mutex<Option<(Stamp, Arc<T>)>>>;
+
+// Values derived from files, rebuilt when a file's mtime or length changes. The
+// shared lock is only held to find a file's slot, so a slow build holds up requests
+// for that file alone. Once the total weight passes `limit` the least recently used
+// values are dropped; a value heavier than `limit` is never kept.
+pub struct FileCache<T> {
+    slots: Arc<Mutex<Slots<T>>>,
+    limit: usize,
+    weight: fn(&T) -> usize,
+}
+
+impl<T> Clone for FileCache<T> {
+    fn clone(&self) -> Self {
+        FileCache {
+            slots: self.slots.clone(),
+            limit: self.limit,
+            weight: self.weight,
+        }
+    }
+}
+
+struct Slots<T> {
+    entries: HashMap<PathBuf, Cached<T>>,
+    weight: usize,
+    clock: u64,
+}
+
+struct Cached<T> {
+    slot: Slot<T>,
+    weight: usize,
+    used: u64,
+}
+
+impl<T> FileCache<T> {
+    pub fn new(limit: usize, weight: fn(&T) -> usize) -> FileCache<T> {
+        FileCache {
+            slots: Arc::new(Mutex::new(Slots {
+                entries: HashMap::new(),
+                weight: 0,
+                clock: 0,
+            })),
+            limit,
+            weight,
+        }
+    }
+
+    pub fn get(
+        &self,
+        path: &Path,
+        build: impl FnOnce(&Path) -> io::Result<T>,
+    ) -> io::Result<Arc<T>> {
+        let meta = std::fs::metadata(path)?;
+        let stamp = (meta.modified()?, meta.len());
+        let slot = lock(&self.slots).slot(path);
+        let mut current = lock(&slot);
+        if let Some((built, value)) = &*current
+            && *built == stamp
+        {
+            return Ok(value.clone());
+        }
+        let value = match build(path) {
+            Ok(value) => Arc::new(value),
+            Err(e) => {
+                drop(current);
+                lock(&self.slots).failed(path, &slot);
+                return Err(e);
+            }
+        };
+        *current = Some((stamp, value.clone()));
+        drop(current);
+        let weight = (self.weight)(&value);
+        lock(&self.slots).loaded(path, &slot, weight, self.limit);
+        Ok(value)
+    }
+}
+
+impl<T> Slots<T> {
+    fn slot(&mut self, path: &Path) -> Slot<T> {
+        self.clock += 1;
+        let cached = self
+            .entries
+            .entry(path.to_owned())
+            .or_insert_with(|| Cached {
+                slot: Default::default(),
+                weight: 0,
+                used: 0,
+            });
+        cached.used = self.clock;
+        cached.slot.clone()
+    }
+
+    fn failed(&mut self, path: &Path, slot: &Slot<T>) {
+        if let Some(cached) = self.entries.get(path)
+            && Arc::ptr_eq(&cached.slot, slot)
+        {
+            self.weight -= cached.weight;
+            self.entries.remove(path);
+        }
+    }
+
+    fn loaded(&mut self, path: &Path, slot: &Slot<T>, weight: usize, limit: usize) {
+        // The slot may have been evicted while its value was being built.
+        if let Some(cached) = self.entries.get_mut(path)
+            && Arc::ptr_eq(&cached.slot, slot)
+        {
+            self.weight = self.weight - cached.weight + weight;
+            cached.weight = weight;
+        }
+        while self.weight > limit {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .filter(|(_, c)| c.weight > 0)
+                .min_by_key(|(_, c)| c.used)
+                .map(|(p, _)| p.clone())
+            else {
+                break;
+            };
+            if let Some(old) = self.entries.remove(&oldest) {
+                self.weight -= old.weight;
+            }
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn rebuilds_changed_files_and_evicts_old_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        std::fs::write(&a, "aaaaaa").unwrap();
+        std::fs::write(&b, "bbbbbb").unwrap();
+        let cache = FileCache::new(9, |value: &String| value.len());
+        let builds = Cell::new(0);
+        let get = |path: &Path| {
+            cache
+                .get(path, |path| {
+                    builds.set(builds.get() + 1);
+                    std::fs::read_to_string(path)
+                })
+                .unwrap()
+        };
+        assert_eq!(*get(&a), "aaaaaa");
+        assert_eq!(*get(&a), "aaaaaa");
+        assert_eq!(builds.get(), 1);
+        std::fs::write(&a, "aaaa").unwrap();
+        assert_eq!(*get(&a), "aaaa");
+        assert_eq!(builds.get(), 2);
+        // Together the two values weigh more than the limit, so `a` goes.
+        get(&b);
+        get(&b);
+        assert_eq!(builds.get(), 3);
+        get(&a);
+        assert_eq!(builds.get(), 4);
+        let missing = dir.path().join("missing");
+        assert!(cache.get(&missing, |p| std::fs::read_to_string(p)).is_err());
+    }
+}
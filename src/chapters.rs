@@ -0,0 +1,180 @@
+use crate::subtitle::Cue;
+use crate::{parse_duration_flex, serialize_duration};
+use rocket::serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use serde_json::{Value, json};
+use std::fmt::Write;
+use std::time::Duration;
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Chapter {
+    #[serde(
+        deserialize_with = "parse_duration_flex",
+        serialize_with = "serialize_duration"
+    )]
+    #[schemars(schema_with = "crate::openapi::duration_schema")]
+    pub start: Duration,
+    pub title: String,
+}
+
+// A zero duration means the length is unknown, so only ordering is enforced.
+fn in_range(start: Duration, duration: Duration) -> bool {
+    duration.is_zero() || start < duration
+}
+
+pub fn check(chapters: &[Chapter], duration: Duration) -> Result<(), String> {
+    let mut previous = None;
+    for chapter in chapters {
+        if chapter.title.trim().is_empty() {
+            return Err("chapter title must not be empty".into());
+        }
+        if !in_range(chapter.start, duration) {
+            return Err(format!("chapter {:?} starts after the end", chapter.title));
+        }
+        if previous.is_some_and(|p| chapter.start <= p) {
+            return Err(format!("chapter {:?} is out of order", chapter.title));
+        }
+        previous = Some(chapter.start);
+    }
+    Ok(())
+}
+
+// Picks up `00:12:34 Topic` style lines (also `12:34 - Topic`, `[1:02:03] Topic`).
+// Lines that fall outside the recording or go backwards are ignored, since
+// descriptions are free text rather than validated data.
+pub fn from_description(description: &str, duration: Duration) -> Vec<Chapter> {
+    let mut chapters: Vec<Chapter> = Vec::new();
+    for line in description.lines() {
+        let line = line.trim_start().trim_start_matches(['[', '(']);
+        let stamp_len = line
+            .find(|c: char| !c.is_ascii_digit() && c != ':')
+            .unwrap_or(line.len());
+        let Some(start) = parse_clock(&line[..stamp_len]) else {
+            continue;
+        };
+        let title = line[stamp_len..]
+            .trim_start_matches([']', ')'])
+            .trim_start_matches(|c: char| c.is_whitespace() || matches!(c, '-' | '–' | '—' | ':'))
+            .trim();
+        if title.is_empty()
+            || !in_range(start, duration)
+            || chapters.last().is_some_and(|c| start <= c.start)
+        {
+            continue;
+        }
+        chapters.push(Chapter {
+            start,
+            title: title.to_owned(),
+        });
+    }
+    chapters
+}
+
+fn parse_clock(s: &str) -> Option<Duration> {
+    let parts: Vec<_> = s.split(':').collect();
+    if !(2..=3).contains(&parts.len()) || parts[1..].iter().any(|p| p.len() != 2) {
+        return None;
+    }
+    let mut secs = 0;
+    for part in parts {
+        secs = secs * 60 + part.parse::<u64>().ok()?;
+    }
+    Some(Duration::from_secs(secs))
+}
+
+fn ends(chapters: &[Chapter], duration: Duration) -> impl Iterator<Item = (&Chapter, Duration)> {
+    chapters.iter().enumerate().map(move |(i, chapter)| {
+        let end = chapters.get(i + 1).map_or(duration, |next| next.start);
+        (chapter, end.max(chapter.start))
+    })
+}
+
+pub fn cues(chapters: &[Chapter], duration: Duration) -> Vec<Cue> {
+    ends(chapters, duration)
+        .map(|(chapter, end)| Cue {
+            start_ms: chapter.start.as_millis() as u64,
+            end_ms: end.as_millis() as u64,
+            text: chapter.title.clone(),
+        })
+        .collect()
+}
+
+// https://github.com/Podcastindex-org/podcast-namespace/blob/main/docs/examples/chapters/jsonChapters.md
+pub fn json(chapters: &[Chapter]) -> Value {
+    let chapters: Vec<_> = chapters
+        .iter()
+        .map(|c| json!({ "startTime": c.start.as_secs_f64(), "title": c.title }))
+        .collect();
+    json!({ "version": "1.2.0", "chapters": chapters })
+}
+
+// https://ffmpeg.org/ffmpeg-formats.html#Metadata-1
+pub fn ffmetadata(title: &str, chapters: &[Chapter], duration: Duration) -> String {
+    let mut out = format!(";FFMETADATA1\ntitle={}\n", escape(title));
+    for (chapter, end) in ends(chapters, duration) {
+        let _ = write!(
+            out,
+            "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            chapter.start.as_millis(),
+            end.as_millis(),
+            escape(&chapter.title)
+        );
+    }
+    out
+}
+
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clocks() {
+        assert_eq!(parse_clock("12:34"), Some(Duration::from_secs(754)));
+        assert_eq!(parse_clock("1:02:03"), Some(Duration::from_secs(3723)));
+        assert_eq!(parse_clock("00:00"), Some(Duration::ZERO));
+        assert_eq!(parse_clock("12"), None);
+        assert_eq!(parse_clock("1:2"), None);
+        assert_eq!(parse_clock("1:02:03:04"), None);
+        assert_eq!(parse_clock(":30"), None);
+    }
+
+    #[test]
+    fn descriptions() {
+        let description = "Setlist below\n\
+                           00:00 Intro\n\
+                           [05:30] - Tutorial: part one\n\
+                           (1:02:03) Late night\n\
+                           03:00 Goes backwards\n\
+                           2:00:00 Past the end\n\
+                           1:30:00\n\
+                           see you at 12:00 tomorrow";
+        let chapters = from_description(description, Duration::from_secs(2 * 3600));
+        let found: Vec<_> = chapters
+            .iter()
+            .map(|c| (c.start.as_secs(), c.title.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (0, "Intro"),
+                (330, "Tutorial: part one"),
+                (3723, "Late night")
+            ]
+        );
+        assert!(check(&chapters, Duration::from_secs(2 * 3600)).is_ok());
+        assert!(check(&chapters, Duration::from_secs(3600)).is_err());
+        assert_eq!(from_description("90:00 Long", Duration::ZERO).len(), 1);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::cache::FileCache;
+use rocket::serde::Serialize;
+use schemars::JsonSchema;
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+pub const CHAT_SUFFIX: &str = "live_chat.json";
+
+const CHECKPOINT_LINES: usize = 256;
+
+// Checkpoints (16 bytes each) are cached for up to this many across all logs.
+const CACHE_CHECKPOINTS: usize = 1 << 20;
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Message {
+    pub offset_ms: u64,
+    pub id: String,
+    pub author: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_id: Option<String>,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<String>,
+}
+
+impl Message {
+    // yt-dlp writes one `replayChatItemAction` per line; only chat messages and
+    // super chats carry text, everything else (tickers, banners) is skipped.
+    pub fn parse(line: &str) -> Option<Message> {
+        let value: Value = serde_json::from_str(line).ok()?;
+        let replay = value.get("replayChatItemAction")?;
+        let offset_ms = replay
+            .get("videoOffsetTimeMsec")
+            .and_then(Value::as_str)?
+            .parse()
+            .ok()?;
+        let item = replay
+            .get("actions")?
+            .as_array()?
+            .iter()
+            .find_map(|a| a.pointer("/addChatItemAction/item"))?;
+        let renderer = item
+            .get("liveChatTextMessageRenderer")
+            .or_else(|| item.get("liveChatPaidMessageRenderer"))?;
+        let text = renderer
+            .pointer("/message/runs")
+            .and_then(Value::as_array)
+            .map(|runs| runs.iter().map(run_text).collect())
+            .unwrap_or_default();
+        Some(Message {
+            offset_ms,
+            id: str_at(renderer, "/id").unwrap_or_default(),
+            author: str_at(renderer, "/authorName/simpleText").unwrap_or_default(),
+            author_id: str_at(renderer, "/authorExternalChannelId"),
+            text,
+            amount: str_at(renderer, "/purchaseAmountText/simpleText"),
+        })
+    }
+}
+
+fn run_text(run: &Value) -> String {
+    if let Some(text) = run.get("text").and_then(Value::as_str) {
+        return text.to_owned();
+    }
+    let emoji = run.get("emoji");
+    emoji
+        .and_then(|e| e.pointer("/shortcuts/0"))
+        .or_else(|| emoji.and_then(|e| e.get("emojiId")))
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_owned()
+}
+
+fn str_at(value: &Value, pointer: &str) -> Option<String> {
+    value.pointer(pointer)?.as_str().map(str::to_owned)
+}
+
+// Reads the playback offset without parsing the whole line.
+fn line_offset(line: &str) -> Option<u64> {
+    const KEY: &str = "\"videoOffsetTimeMsec\"";
+    let rest = &line[line.find(KEY)? + KEY.len()..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+    rest[..rest.find('"')?].parse().ok()
+}
+
+// A sparse map from playback offset to byte position, so a window can be read by
+// seeking close to its start instead of scanning the log from the beginning.
+pub struct ChatIndex {
+    checkpoints: Vec<(u64, u64)>,
+    pub messages: usize,
+}
+
+impl ChatIndex {
+    fn build(path: &Path) -> io::Result<ChatIndex> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut checkpoints = Vec::new();
+        let mut buf = Vec::new();
+        let (mut pos, mut lines) = (0, 0);
+        loop {
+            buf.clear();
+            let n = reader.read_until(b'\n', &mut buf)?;
+            if n == 0 {
+                break;
+            }
+            let line = String::from_utf8_lossy(&buf);
+            if let Some(offset) = line_offset(&line) {
+                if lines % CHECKPOINT_LINES == 0 {
+                    checkpoints.push((offset, pos));
+                }
+                lines += 1;
+            }
+            pos += n as u64;
+        }
+        Ok(ChatIndex {
+            checkpoints,
+            messages: lines,
+        })
+    }
+
+    fn seek_position(&self, from_ms: u64) -> u64 {
+        let i = self
+            .checkpoints
+            .partition_point(|&(offset, _)| offset < from_ms);
+        i.checked_sub(1).map_or(0, |i| self.checkpoints[i].1)
+    }
+}
+
+pub fn scan(path: &Path, mut f: impl FnMut(Message)) -> io::Result<()> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buf = Vec::new();
+    while reader.read_until(b'\n', &mut buf)? > 0 {
+        if let Some(message) = Message::parse(&String::from_utf8_lossy(&buf)) {
+            f(message);
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+pub struct Window {
+    pub messages: Vec<Message>,
+    pub next: Option<u64>,
+}
+
+// Building an index reads the whole log, so callers run this off the async workers.
+#[derive(Clone)]
+pub struct ChatStore {
+    cache: FileCache<ChatIndex>,
+}
+
+impl Default for ChatStore {
+    fn default() -> Self {
+        ChatStore {
+            cache: FileCache::new(CACHE_CHECKPOINTS, |index| index.checkpoints.len()),
+        }
+    }
+}
+
+impl ChatStore {
+    pub fn index(&self, path: &Path) -> io::Result<Arc<ChatIndex>> {
+        self.cache.get(path, ChatIndex::build)
+    }
+
+    // Returns messages with `from <= offset < to`, starting at `cursor` (a byte
+    // position from a previous window) when given.
+    pub fn window(
+        &self,
+        path: &Path,
+        from_ms: u64,
+        to_ms: Option<u64>,
+        cursor: Option<u64>,
+        limit: usize,
+    ) -> io::Result<Window> {
+        let start = match cursor {
+            Some(pos) => pos,
+            None => self.index(path)?.seek_position(from_ms),
+        };
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(start))?;
+        let mut reader = BufReader::new(file);
+        let mut messages = Vec::new();
+        let mut buf = Vec::new();
+        let mut pos = start;
+        loop {
+            buf.clear();
+            let n = reader.read_until(b'\n', &mut buf)?;
+            let line = String::from_utf8_lossy(&buf);
+            if n == 0 {
+                return Ok(Window {
+                    messages,
+                    next: None,
+                });
+            }
+            let Some(offset) = line_offset(&line) else {
+                pos += n as u64;
+                continue;
+            };
+            if to_ms.is_some_and(|to| offset >= to) {
+                return Ok(Window {
+                    messages,
+                    next: None,
+                });
+            }
+            if offset >= from_ms
+                && let Some(message) = Message::parse(&line)
+            {
+                if messages.len() == limit {
+                    return Ok(Window {
+                        messages,
+                        next: Some(pos),
+                    });
+                }
+                messages.push(message);
+            }
+            pos += n as u64;
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use crate::chat::Message;
+use crate::fulltext::tokenize;
+use log::warn;
+use rocket::serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+
+// Loaded indexes are kept up to this many postings (8 bytes each); the least
+// recently searched are dropped first. Logs larger than that are never cached.
+const CACHE_POSTINGS: usize = 16 << 20;
+
+// Postings are byte positions of message lines within the chat log, so a hit can
+// be read back with a single seek.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct FileIndex {
+    modified_ns: u64,
+    len: u64,
+    terms: BTreeMap<String, Vec<u64>>,
+    authors: HashMap<String, Vec<u64>>,
+}
+
+impl FileIndex {
+    fn build(log: &Path, (modified_ns, len): (u64, u64)) -> io::Result<FileIndex> {
+        let mut index = FileIndex {
+            modified_ns,
+            len,
+            terms: BTreeMap::new(),
+            authors: HashMap::new(),
+        };
+        let mut reader = BufReader::new(File::open(log)?);
+        let mut buf = Vec::new();
+        let mut pos = 0;
+        loop {
+            buf.clear();
+            let n = reader.read_until(b'\n', &mut buf)?;
+            if n == 0 {
+                break;
+            }
+            if let Some(message) = Message::parse(&String::from_utf8_lossy(&buf)) {
+                let mut terms: Vec<_> = tokenize(&message.text)
+                    .into_iter()
+                    .map(|(_, t)| t)
+                    .collect();
+                terms.sort();
+                terms.dedup();
+                for term in terms {
+                    index.terms.entry(term).or_default().push(pos);
+                }
+                let authors = [Some(message.author.to_lowercase()), message.author_id];
+                for author in authors.into_iter().flatten() {
+                    index.authors.entry(author).or_default().push(pos);
+                }
+            }
+            pos += n as u64;
+        }
+        Ok(index)
+    }
+
+    fn postings(&self) -> usize {
+        self.terms
+            .values()
+            .chain(self.authors.values())
+            .map(Vec::len)
+            .sum()
+    }
+
+    // Every term must match; the last one also matches as a prefix so that
+    // results appear while a query is still being typed.
+    fn matches(&self, terms: &[String], author: Option<&str>) -> Vec<u64> {
+        let mut sets: Vec<Vec<u64>> = Vec::new();
+        if let Some((last, rest)) = terms.split_last() {
+            for term in rest {
+                sets.push(self.terms.get(term).cloned().unwrap_or_default());
+            }
+            let mut prefixed: Vec<u64> = self
+                .terms
+                .range(last.clone()..)
+                .take_while(|(t, _)| t.starts_with(last.as_str()))
+                .flat_map(|(_, p)| p.iter().copied())
+                .collect();
+            prefixed.sort_unstable();
+            prefixed.dedup();
+            sets.push(prefixed);
+        }
+        if let Some(author) = author {
+            let postings = self
+                .authors
+                .get(author)
+                .or_else(|| self.authors.get(&author.to_lowercase()));
+            sets.push(postings.cloned().unwrap_or_default());
+        }
+        sets.into_iter()
+            .reduce(|a, b| intersect(&a, &b))
+            .unwrap_or_default()
+    }
+}
+
+fn intersect(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let (mut i, mut j, mut out) = (0, 0, Vec::new());
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                out.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    out
+}
+
+// Just the header of a stored index, read without holding its postings in memory.
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Stamp {
+    modified_ns: u64,
+    len: u64,
+}
+
+fn stamp(log: &Path) -> io::Result<(u64, u64)> {
+    let meta = fs::metadata(log)?;
+    let modified = meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok((modified.as_nanos() as u64, meta.len()))
+}
+
+#[derive(Clone)]
+pub struct ChatLog {
+    pub kind: String,
+    pub id: String,
+    pub path: PathBuf,
+}
+
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<PathBuf, Cached>,
+    postings: usize,
+    clock: u64,
+}
+
+struct Cached {
+    index: Arc<FileIndex>,
+    postings: usize,
+    used: u64,
+}
+
+impl Cache {
+    fn get(&mut self, path: &Path, stamp: (u64, u64)) -> Option<Arc<FileIndex>> {
+        self.clock += 1;
+        let cached = self.entries.get_mut(path)?;
+        if (cached.index.modified_ns, cached.index.len) != stamp {
+            return None;
+        }
+        cached.used = self.clock;
+        Some(cached.index.clone())
+    }
+
+    fn insert(&mut self, path: PathBuf, index: Arc<FileIndex>) {
+        let postings = index.postings();
+        if let Some(old) = self.entries.remove(&path) {
+            self.postings -= old.postings;
+        }
+        if postings > CACHE_POSTINGS {
+            return;
+        }
+        while self.postings + postings > CACHE_POSTINGS {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, c)| c.used)
+                .map(|(p, _)| p.clone())
+            else {
+                break;
+            };
+            if let Some(old) = self.entries.remove(&oldest) {
+                self.postings -= old.postings;
+            }
+        }
+        self.postings += postings;
+        let used = self.clock;
+        self.entries.insert(
+            path,
+            Cached {
+                index,
+                postings,
+                used,
+            },
+        );
+    }
+}
+
+// Per-log indexes are persisted under `dir` and only rebuilt when their log changes.
+// Building and loading them reads whole files, so callers run this off the async
+// workers.
+#[derive(Clone)]
+pub struct ChatSearch {
+    dir: PathBuf,
+    cache: Arc<Mutex<Cache>>,
+}
+
+impl ChatSearch {
+    pub fn new(dir: PathBuf) -> ChatSearch {
+        ChatSearch {
+            dir,
+            cache: Default::default(),
+        }
+    }
+
+    fn stored(&self, log: &ChatLog) -> PathBuf {
+        self.dir
+            .join(&log.kind)
+            .join(&log.id)
+            .with_extension("json")
+    }
+
+    fn file_index(&self, log: &ChatLog) -> io::Result<Arc<FileIndex>> {
+        let stamp = stamp(&log.path)?;
+        let fresh = |i: &FileIndex| (i.modified_ns, i.len) == stamp;
+        if let Some(index) = self.cache.lock().unwrap().get(&log.path, stamp) {
+            return Ok(index);
+        }
+        let stored = self.stored(log);
+        let index = match fs::read(&stored)
+            .ok()
+            .and_then(|b| serde_json::from_slice::<FileIndex>(&b).ok())
+        {
+            Some(index) if fresh(&index) => index,
+            _ => {
+                let index = FileIndex::build(&log.path, stamp)?;
+                if let Err(e) = self.store(&stored, &index) {
+                    warn!("cannot persist chat index {}: {e}", stored.display());
+                }
+                index
+            }
+        };
+        let index = Arc::new(index);
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(log.path.clone(), index.clone());
+        Ok(index)
+    }
+
+    fn store(&self, path: &Path, index: &FileIndex) -> io::Result<()> {
+        let dir = path.parent().unwrap_or(&self.dir);
+        fs::create_dir_all(dir)?;
+        let tmp = tempfile::NamedTempFile::new_in(dir)?;
+        serde_json::to_writer(&tmp, index)?;
+        tmp.persist(path)?;
+        Ok(())
+    }
+
+    // Brings stored indexes up to date without loading them into the cache.
+    pub fn warm(&self, logs: &[ChatLog]) {
+        for log in logs {
+            let stored = self.stored(log);
+            let current = File::open(&stored)
+                .ok()
+                .and_then(|f| serde_json::from_reader::<_, Stamp>(BufReader::new(f)).ok())
+                .map(|s| (s.modified_ns, s.len));
+            let result = stamp(&log.path).and_then(|stamp| {
+                if current != Some(stamp) {
+                    self.store(&stored, &FileIndex::build(&log.path, stamp)?)?;
+                }
+                Ok(())
+            });
+            if let Err(e) = result {
+                warn!("cannot index chat {}: {e}", log.path.display());
+            }
+        }
+    }
+
+    pub fn search(
+        &self,
+        log: &ChatLog,
+        terms: &[String],
+        author: Option<&str>,
+        limit: usize,
+    ) -> io::Result<Vec<Message>> {
+        let positions = self.file_index(log)?.matches(terms, author);
+        let mut reader = BufReader::new(File::open(&log.path)?);
+        let mut messages = Vec::new();
+        let mut buf = Vec::new();
+        for pos in positions.into_iter().take(limit) {
+            reader.seek(SeekFrom::Start(pos))?;
+            buf.clear();
+            reader.read_until(b'\n', &mut buf)?;
+            messages.extend(Message::parse(&String::from_utf8_lossy(&buf)));
+        }
+        Ok(messages)
+    }
+}
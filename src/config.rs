@@ -0,0 +1,213 @@
+use crate::is_ident;
+use rocket::serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// First segments of the static `/api` routes, which would shadow a kind's listing.
+const RESERVED: &[&str] = &[
+    "admin",
+    "chat",
+    "docs",
+    "search",
+    "songs",
+    "status",
+    "transcripts",
+];
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[serde(default)]
+pub struct ArchiveConfig {
+    pub root: PathBuf,
+    pub kinds: BTreeMap<String, KindConfig>,
+    pub tokens: Vec<String>,
+    pub public_fields: Option<Vec<String>>,
+    pub chat_index: Option<PathBuf>,
+    pub base_url: Option<String>,
+    pub docs: bool,
+    // A Redoc standalone bundle, served to the docs page; required with `docs`.
+    pub redoc: Option<PathBuf>,
+    pub podcast: PodcastConfig,
+    // Poll kind directories instead of relying on native events, for filesystems
+    // such as NFS that never deliver them.
+    pub poll: bool,
+}
+
+// Channel artwork and category, both required by Apple Podcasts. A relative
+// image path is resolved against the base URL.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[serde(default)]
+pub struct PodcastConfig {
+    pub image: Option<String>,
+    pub category: String,
+    pub subcategory: Option<String>,
+}
+
+impl Default for PodcastConfig {
+    fn default() -> Self {
+        PodcastConfig {
+            image: None,
+            category: "Leisure".to_owned(),
+            subcategory: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct KindConfig {
+    pub name: Option<String>,
+    pub dir: Option<PathBuf>,
+    #[serde(default)]
+    pub order: i64,
+    pub parent: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct KindInfo<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    pub order: i64,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        let kinds = ["vods", "highlights", "clips", "rplay"]
+            .into_iter()
+            .zip(0..)
+            .map(|(id, order)| {
+                let parent = matches!(id, "highlights" | "clips").then(|| "vods".to_owned());
+                let kind = KindConfig {
+                    name: None,
+                    dir: None,
+                    order,
+                    parent,
+                };
+                (id.to_owned(), kind)
+            })
+            .collect();
+        ArchiveConfig {
+            root: PathBuf::from("."),
+            kinds,
+            tokens: Vec::new(),
+            public_fields: None,
+            chat_index: None,
+            base_url: None,
+            docs: false,
+            redoc: None,
+            podcast: PodcastConfig::default(),
+            poll: false,
+        }
+    }
+}
+
+impl ArchiveConfig {
+    pub fn kinds(&self) -> Vec<KindInfo<'_>> {
+        let mut kinds: Vec<_> = self
+            .kinds
+            .iter()
+            .map(|(id, kind)| KindInfo {
+                id,
+                name: kind.name.as_deref().unwrap_or(id),
+                order: kind.order,
+            })
+            .collect();
+        kinds.sort_by_key(|k| k.order);
+        kinds
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        for (id, kind) in &self.kinds {
+            if !is_ident(id) {
+                return Err(format!("kind name {id:?} must be alphanumeric, '-' or '_'"));
+            }
+            if RESERVED.contains(&id.as_str()) {
+                return Err(format!("kind name {id:?} is reserved for an API route"));
+            }
+            match &kind.parent {
+                Some(parent) if !self.contains(parent) => {
+                    return Err(format!(
+                        "parent {parent:?} of kind {id:?} is not configured"
+                    ));
+                }
+                _ => {}
+            }
+        }
+        if self.docs && self.redoc.is_none() {
+            return Err("docs requires redoc, the path of a Redoc standalone bundle".into());
+        }
+        Ok(())
+    }
+
+    pub fn name<'a>(&'a self, kind: &'a str) -> Option<&'a str> {
+        Some(self.kinds.get(kind)?.name.as_deref().unwrap_or(kind))
+    }
+
+    pub fn parent(&self, kind: &str) -> Option<&str> {
+        self.kinds.get(kind)?.parent.as_deref()
+    }
+
+    pub fn contains(&self, kind: &str) -> bool {
+        self.kinds.contains_key(kind)
+    }
+
+    pub fn dir(&self, kind: &str) -> Option<PathBuf> {
+        let config = self.kinds.get(kind)?;
+        Some(
+            self.root
+                .join(config.dir.as_deref().unwrap_or(Path::new(kind))),
+        )
+    }
+
+    pub fn chat_index(&self) -> PathBuf {
+        self.chat_index
+            .clone()
+            .unwrap_or_else(|| self.root.join(".chat-index"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_kind(id: &str, parent: Option<&str>) -> ArchiveConfig {
+        let mut config = ArchiveConfig::default();
+        let kind = KindConfig {
+            name: None,
+            dir: None,
+            order: 0,
+            parent: parent.map(str::to_owned),
+        };
+        config.kinds.insert(id.to_owned(), kind);
+        config
+    }
+
+    #[test]
+    fn kind_names() {
+        assert!(ArchiveConfig::default().check().is_ok());
+        assert!(with_kind("karaoke", Some("vods")).check().is_ok());
+        assert!(with_kind("karaoke", Some("nope")).check().is_err());
+        assert!(with_kind("../etc", None).check().is_err());
+        for reserved in RESERVED {
+            assert!(with_kind(reserved, None).check().is_err(), "{reserved}");
+        }
+    }
+
+    #[test]
+    fn kind_dirs() {
+        let mut config = with_kind("karaoke", None);
+        config.root = PathBuf::from("/srv/archive");
+        config.kinds.get_mut("karaoke").unwrap().dir = Some(PathBuf::from("music/karaoke"));
+        assert_eq!(
+            config.dir("karaoke"),
+            Some(PathBuf::from("/srv/archive/music/karaoke"))
+        );
+        assert_eq!(config.dir("vods"), Some(PathBuf::from("/srv/archive/vods")));
+        assert_eq!(config.dir("nope"), None);
+        let order: Vec<_> = config.kinds().iter().map(|k| k.id).collect();
+        assert_eq!(order, ["karaoke", "vods", "highlights", "clips", "rplay"]);
+    }
+}
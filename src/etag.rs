@@ -0,0 +1,97 @@
+use rocket::http::{Header, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, Responder, Response};
+use sha2::{Digest, Sha256};
+
+pub fn etag(bytes: &[u8]) -> String {
+    let digest = Sha256::digest(bytes);
+    let hex: String = digest[..16].iter().map(|b| format!("{b:02x}")).collect();
+    format!("\"{hex}\"")
+}
+
+pub struct Preconditions<'r> {
+    if_match: Option<&'r str>,
+    if_none_match: Option<&'r str>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Preconditions<'r> {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Preconditions {
+            if_match: req.headers().get_one("If-Match"),
+            if_none_match: req.headers().get_one("If-None-Match"),
+        })
+    }
+}
+
+impl Preconditions<'_> {
+    pub fn not_modified(&self, etag: &str) -> bool {
+        self.if_none_match
+            .is_some_and(|h| matches(h, etag, |t, e| weak(t) == weak(e)))
+    }
+
+    // Writes must name the version they were based on, otherwise two editors
+    // silently overwrite each other. `current` is an `Entry-Version`, not an `ETag`.
+    pub fn check(&self, current: &str) -> Result<(), Status> {
+        match self.if_match {
+            None => Err(Status::PreconditionRequired),
+            Some(h) if matches(h, current, |t, e| t == e) => Ok(()),
+            Some(_) => Err(Status::PreconditionFailed),
+        }
+    }
+}
+
+fn matches(header: &str, etag: &str, eq: fn(&str, &str) -> bool) -> bool {
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|t| t == "*" || eq(t, etag))
+}
+
+fn weak(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+pub enum Tagged<R> {
+    Fresh(String, R),
+    NotModified(String),
+}
+
+impl<R> Tagged<R> {
+    pub fn new(etag: String, body: R, pre: &Preconditions) -> Self {
+        if pre.not_modified(&etag) {
+            Tagged::NotModified(etag)
+        } else {
+            Tagged::Fresh(etag, body)
+        }
+    }
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Tagged<R> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'o> {
+        match self {
+            Tagged::Fresh(etag, body) => Response::build_from(body.respond_to(req)?)
+                .header(Header::new("ETag", etag))
+                .ok(),
+            Tagged::NotModified(etag) => Response::build()
+                .status(Status::NotModified)
+                .header(Header::new("ETag", etag))
+                .ok(),
+        }
+    }
+}
+
+// The version of a stored entry file, which writes check `If-Match` against. It
+// is kept apart from `ETag` because an entry response also embeds sidecars and
+// the parent entry, which change without the file changing.
+pub struct Versioned<R>(pub String, pub R);
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Versioned<R> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'o> {
+        Response::build_from(self.1.respond_to(req)?)
+            .header(Header::new("Entry-Version", self.0))
+            .ok()
+    }
+}
@@ -0,0 +1,234 @@
+use crate::Entry;
+use crate::config::{ArchiveConfig, PodcastConfig};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rocket::request::{FromRequest, Outcome, Request};
+use serde_json::{Value, json};
+use std::fmt::Write;
+
+// Feeds need absolute links; `base_url` wins over the request's Host header,
+// which is only a fallback for setups without a reverse proxy.
+pub struct BaseUrl(pub String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BaseUrl {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let configured = req
+            .rocket()
+            .state::<ArchiveConfig>()
+            .and_then(|c| c.base_url.as_deref());
+        let base = match configured {
+            Some(base) => base.trim_end_matches('/').to_owned(),
+            None => match req.host() {
+                Some(host) => format!("http://{host}"),
+                None => String::new(),
+            },
+        };
+        Outcome::Success(BaseUrl(base))
+    }
+}
+
+pub struct Feed<'a> {
+    pub title: &'a str,
+    pub link: String,
+    pub self_url: String,
+    pub items: Vec<Item<'a>>,
+}
+
+pub struct Item<'a> {
+    pub url: String,
+    pub entry: &'a Entry,
+    pub enclosure: Option<Enclosure>,
+    pub chapters_url: Option<String>,
+}
+
+pub struct Enclosure {
+    pub url: String,
+    pub length: u64,
+    pub mime: &'static str,
+}
+
+impl Feed<'_> {
+    fn updated(&self) -> DateTime<Utc> {
+        self.items
+            .iter()
+            .map(|i| i.entry.created_at)
+            .max()
+            .unwrap_or_else(Utc::now)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// `hh:mm:ss`, the form podcast apps understand for `itunes:duration`.
+fn clock(secs: u64) -> String {
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+pub fn rss(feed: &Feed) -> String {
+    let mut out = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        "\n",
+        r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">"#,
+        "\n<channel>\n"
+    ));
+    let _ = write!(
+        out,
+        "<title>{}</title>\n<link>{}</link>\n<description>{}</description>\n\
+         <atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>\n\
+         <lastBuildDate>{}</lastBuildDate>\n",
+        escape(feed.title),
+        escape(&feed.link),
+        escape(feed.title),
+        escape(&feed.self_url),
+        feed.updated().to_rfc2822()
+    );
+    for item in &feed.items {
+        rss_item(&mut out, item, "");
+    }
+    out.push_str("</channel>\n</rss>\n");
+    out
+}
+
+fn rss_item(out: &mut String, item: &Item, extra: &str) {
+    let entry = item.entry;
+    let _ = write!(
+        out,
+        "<item>\n<title>{}</title>\n<link>{url}</link>\n<guid isPermaLink=\"true\">{url}</guid>\n\
+         <description>{}</description>\n<pubDate>{}</pubDate>\n\
+         <itunes:duration>{}</itunes:duration>\n{extra}</item>\n",
+        escape(&entry.title),
+        escape(&entry.description),
+        entry.created_at.to_rfc2822(),
+        clock(entry.duration.as_secs()),
+        url = escape(&item.url),
+    );
+}
+
+// Only items with a media file are published; everything else is not playable.
+pub fn podcast(feed: &Feed, config: &PodcastConfig, image: Option<&str>) -> String {
+    let mut out = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        "\n",
+        r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd" xmlns:podcast="https://podcastindex.org/namespace/1.0">"#,
+        "\n<channel>\n"
+    ));
+    let _ = write!(
+        out,
+        "<title>{title}</title>\n<link>{}</link>\n<description>{title}</description>\n\
+         <atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>\n\
+         <lastBuildDate>{}</lastBuildDate>\n<itunes:author>{title}</itunes:author>\n\
+         <itunes:explicit>false</itunes:explicit>\n",
+        escape(&feed.link),
+        escape(&feed.self_url),
+        feed.updated().to_rfc2822(),
+        title = escape(feed.title),
+    );
+    if let Some(image) = image {
+        let _ = writeln!(out, "<itunes:image href=\"{}\"/>", escape(image));
+    }
+    let category = escape(&config.category);
+    match &config.subcategory {
+        Some(sub) => {
+            let _ = writeln!(
+                out,
+                "<itunes:category text=\"{category}\">\n<itunes:category text=\"{}\"/>\n</itunes:category>",
+                escape(sub)
+            );
+        }
+        None => {
+            let _ = writeln!(out, "<itunes:category text=\"{category}\"/>");
+        }
+    }
+    for item in &feed.items {
+        let Some(enclosure) = &item.enclosure else {
+            continue;
+        };
+        let mut extra = format!(
+            "<enclosure url=\"{}\" length=\"{}\" type=\"{}\"/>\n\
+             <itunes:title>{}</itunes:title>\n<itunes:summary>{}</itunes:summary>\n",
+            escape(&enclosure.url),
+            enclosure.length,
+            enclosure.mime,
+            escape(&item.entry.title),
+            escape(&item.entry.description),
+        );
+        if let Some(url) = &item.chapters_url {
+            let _ = writeln!(
+                extra,
+                "<podcast:chapters url=\"{}\" type=\"application/json+chapters\"/>",
+                escape(url)
+            );
+        }
+        rss_item(&mut out, item, &extra);
+    }
+    out.push_str("</channel>\n</rss>\n");
+    out
+}
+
+pub fn atom(feed: &Feed) -> String {
+    let mut out = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        "\n",
+        r#"<feed xmlns="http://www.w3.org/2005/Atom" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">"#,
+        "\n"
+    ));
+    let _ = write!(
+        out,
+        "<title>{}</title>\n<id>{link}</id>\n<link href=\"{link}\"/>\n\
+         <link rel=\"self\" type=\"application/atom+xml\" href=\"{}\"/>\n<updated>{}</updated>\n",
+        escape(feed.title),
+        escape(&feed.self_url),
+        feed.updated().to_rfc3339_opts(SecondsFormat::Secs, true),
+        link = escape(&feed.link),
+    );
+    for item in &feed.items {
+        let entry = item.entry;
+        let published = entry.created_at.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let _ = write!(
+            out,
+            "<entry>\n<title>{}</title>\n<id>{url}</id>\n<link href=\"{url}\"/>\n\
+             <published>{published}</published>\n<updated>{published}</updated>\n\
+             <summary>{}</summary>\n<itunes:duration>{}</itunes:duration>\n</entry>\n",
+            escape(&entry.title),
+            escape(&entry.description),
+            clock(entry.duration.as_secs()),
+            url = escape(&item.url),
+        );
+    }
+    out.push_str("</feed>\n");
+    out
+}
+
+// https://www.jsonfeed.org/version/1.1/; the duration goes in a `_kgg` extension.
+pub fn json(feed: &Feed) -> Value {
+    let items: Vec<_> = feed
+        .items
+        .iter()
+        .map(|item| {
+            let entry = item.entry;
+            json!({
+                "id": item.url,
+                "url": item.url,
+                "title": entry.title,
+                "content_text": entry.description,
+                "date_published": entry.created_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+                "_kgg": { "duration_seconds": entry.duration.as_secs_f64() },
+            })
+        })
+        .collect();
+    json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": feed.title,
+        "home_page_url": feed.link,
+        "feed_url": feed.self_url,
+        "items": items,
+    })
+}
@@ -0,0 +1,179 @@
+use crate::Entry;
+use rocket::serde::Serialize;
+use schemars::JsonSchema;
+use std::ops::Range;
+
+const SNIPPET_CHARS: usize = 160;
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Hit {
+    pub kind: String,
+    pub score: f64,
+    pub title: String,
+    pub snippet: String,
+    pub entry: Entry,
+}
+
+pub fn tokenize(text: &str) -> Vec<(Range<usize>, String)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (start, c.is_alphanumeric()) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                tokens.push((s..i, text[s..i].to_lowercase()));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+pub struct Query {
+    terms: Vec<String>,
+}
+
+impl Query {
+    pub fn parse(q: &str) -> Option<Query> {
+        let terms: Vec<_> = tokenize(q).into_iter().map(|(_, t)| t).collect();
+        (!terms.is_empty()).then_some(Query { terms })
+    }
+
+    pub fn hit(&self, kind: &str, entry: &Entry) -> Option<Hit> {
+        let title = tokenize(&entry.title);
+        let description = tokenize(&entry.description);
+        let mut score = 0.0;
+        for term in &self.terms {
+            let s = 3.0 * field_score(term, &title) + field_score(term, &description);
+            if s == 0.0 {
+                return None;
+            }
+            score += s;
+        }
+        Some(Hit {
+            kind: kind.to_owned(),
+            score,
+            title: highlight(&entry.title, &self.matches(&title), 0..entry.title.len()),
+            snippet: self.snippet(&entry.description, &description),
+            entry: entry.clone(),
+        })
+    }
+
+    // Scores free text such as a subtitle cue; every term must match.
+    pub fn text_hit(&self, text: &str) -> Option<(f64, String)> {
+        let tokens = tokenize(text);
+        let mut score = 0.0;
+        for term in &self.terms {
+            let s = field_score(term, &tokens);
+            if s == 0.0 {
+                return None;
+            }
+            score += s;
+        }
+        Some((score, self.snippet(text, &tokens)))
+    }
+
+    fn matches(&self, tokens: &[(Range<usize>, String)]) -> Vec<Range<usize>> {
+        tokens
+            .iter()
+            .filter(|(_, t)| self.terms.iter().any(|term| t.starts_with(term.as_str())))
+            .map(|(r, _)| r.clone())
+            .collect()
+    }
+
+    fn snippet(&self, text: &str, tokens: &[(Range<usize>, String)]) -> String {
+        let matches = self.matches(tokens);
+        let first = matches.first().map_or(0, |r| r.start);
+        let start = floor_char_boundary(text, first.saturating_sub(SNIPPET_CHARS / 4));
+        let start = text[..start]
+            .char_indices()
+            .rfind(|(_, c)| c.is_whitespace())
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let end = floor_char_boundary(text, start + SNIPPET_CHARS);
+        let mut snippet = highlight(text, &matches, start..end);
+        if start > 0 {
+            snippet.insert(0, '…');
+        }
+        if end < text.len() {
+            snippet.push('…');
+        }
+        snippet
+    }
+}
+
+fn field_score(term: &str, tokens: &[(Range<usize>, String)]) -> f64 {
+    tokens
+        .iter()
+        .map(|(_, t)| {
+            if t == term {
+                1.0
+            } else if t.starts_with(term) {
+                0.5
+            } else {
+                0.0
+            }
+        })
+        .sum()
+}
+
+fn highlight(text: &str, matches: &[Range<usize>], window: Range<usize>) -> String {
+    let mut out = String::new();
+    let mut pos = window.start;
+    for m in matches {
+        if m.start < pos || m.end > window.end {
+            continue;
+        }
+        out.push_str(&escape(&text[pos..m.start]));
+        out.push_str("<mark>");
+        out.push_str(&escape(&text[m.clone()]));
+        out.push_str("</mark>");
+        pos = m.end;
+    }
+    out.push_str(&escape(&text[pos..window.end]));
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn floor_char_boundary(text: &str, mut i: usize) -> usize {
+    if i >= text.len() {
+        return text.len();
+    }
+    while !text.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snippets_start_after_wide_spaces() {
+        let query = Query::parse("needle").unwrap();
+        for space in ["\u{3000}", "\u{a0}", " "] {
+            let text = format!("{}{space}{} needle", "x".repeat(10), "y".repeat(60));
+            let (_, snippet) = query.text_hit(&text).unwrap();
+            assert!(snippet.starts_with("…yyy"), "{snippet}");
+            assert!(snippet.ends_with("<mark>needle</mark>"), "{snippet}");
+        }
+    }
+
+    #[test]
+    fn snippets_highlight_prefixes() {
+        let query = Query::parse("nee").unwrap();
+        let (_, snippet) = query.text_hit("a <needle> & 針 needles").unwrap();
+        assert_eq!(
+            snippet,
+            "a &lt;<mark>needle</mark>&gt; &amp; 針 <mark>needles</mark>"
+        );
+    }
+}
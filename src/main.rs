@@ -1,72 +1,1487 @@
-use chrono::{DateTime, Utc};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, NaiveDate, NaiveTime, SecondsFormat, Utc};
 use humantime::parse_duration;
-use rocket::http::Status;
+use log::{error, warn};
+use rocket::fairing::AdHoc;
+use rocket::http::{ContentType, Status};
+use rocket::response::status::{Created, NoContent};
 use rocket::serde::json::Json;
-use rocket::serde::{Deserialize, Deserializer, Serialize, Serializer, de};
-use rocket::{Request, catch, catchers, get, launch, routes};
-use std::cmp::Reverse;
+// `self` is imported because the JsonSchema derive names `serde` directly.
+use rocket::serde::{self, Deserialize, Deserializer, Serialize, Serializer, de};
+use rocket::{
+    Build, Either, Request, Rocket, State, catch, catchers, delete, get, patch, post, put, routes,
+    uri,
+};
+use schemars::JsonSchema;
+use serde_json::{Map, Value};
 use std::ffi::OsStr;
 use std::fs::{DirEntry, read_dir, read_to_string};
+use std::io;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
+use std::{env, fmt, process};
 
-#[launch]
-fn rocket() -> _ {
+mod activity;
+mod auth;
+mod cache;
+mod chapters;
+mod chat;
+mod chatsearch;
+mod config;
+mod etag;
+mod feed;
+mod fulltext;
+mod media;
+mod metrics;
+mod openapi;
+mod problem;
+mod songs;
+mod store;
+mod subtitle;
+
+use activity::Activity;
+use auth::Auth;
+use chapters::Chapter;
+use chat::{CHAT_SUFFIX, ChatStore, Message};
+use chatsearch::{ChatLog, ChatSearch};
+use config::{ArchiveConfig, KindInfo};
+use etag::{Preconditions, Tagged, Versioned, etag};
+use feed::{BaseUrl, Feed};
+use fulltext::{Hit, Query};
+use media::{Media, RangeHeader, Sidecar, content_type, find_media};
+use metrics::{Metrics, RequestMetrics};
+use problem::{Problem, RequestIds};
+use songs::{Song, SongSummary};
+use store::{Index, IndexStats};
+use subtitle::{Format, SubtitleStore};
+
+#[rocket::main]
+async fn main() -> Result<(), Box<rocket::Error>> {
+    if env::args().nth(1).as_deref() == Some("validate") {
+        process::exit(validate());
+    }
+    rocket().launch().await.map_err(Box::new)?;
+    Ok(())
+}
+
+fn rocket() -> Rocket<Build> {
     rocket::build()
-        .mount("/api", routes![index, lists, entry])
+        .manage(ChatStore::default())
+        .manage(SubtitleStore::default())
+        .manage(Metrics::default())
+        .attach(AdHoc::try_on_ignite("Archive", |rocket| async {
+            match rocket.figment().focus("archive").extract::<ArchiveConfig>() {
+                Ok(config) if let Err(e) = config.check() => {
+                    error!("invalid archive configuration: {e}");
+                    Err(rocket)
+                }
+                Ok(config) => {
+                    let rocket = match config.docs {
+                        true => rocket.mount("/api", routes![openapi::docs, openapi::redoc]),
+                        false => rocket,
+                    };
+                    Ok(rocket
+                        .manage(Index::build(&config))
+                        .manage(ChatSearch::new(config.chat_index()))
+                        .manage(config))
+                }
+                Err(e) => {
+                    error!("invalid archive configuration: {e}");
+                    Err(rocket)
+                }
+            }
+        }))
+        .attach(RequestIds)
+        .attach(RequestMetrics)
+        .attach(openapi::fairing())
+        .attach(AdHoc::on_liftoff("Chat index", |rocket| {
+            Box::pin(async move {
+                let (Some(config), Some(index), Some(search)) = (
+                    rocket.state::<ArchiveConfig>(),
+                    rocket.state::<Index>(),
+                    rocket.state::<ChatSearch>(),
+                ) else {
+                    return;
+                };
+                let logs = chat_logs(config, index, |_| true);
+                let search = search.clone();
+                rocket::tokio::task::spawn_blocking(move || search.warm(&logs));
+            })
+        }))
+        .mount(
+            "/api",
+            routes![
+                index,
+                status,
+                openapi::openapi_json,
+                failures,
+                search,
+                chat_search,
+                transcript_search,
+                song_index,
+                lists,
+                rss_feed,
+                atom_feed,
+                json_feed,
+                podcast_feed,
+                entry,
+                children,
+                entry_media,
+                sidecar,
+                entry_chapters,
+                entry_chat,
+                chat_activity,
+                create_entry,
+                replace_entry,
+                update_entry,
+                delete_entry
+            ],
+        )
+        .mount("/", routes![scrape])
         .register("/", catchers![default_catcher])
 }
 
-fn get_entries(path: impl AsRef<Path>) -> Result<Vec<Entry>, Status> {
-    let mut entries: Vec<_> = read_dir(path)
-        .map_err(|_| Status::InternalServerError)?
-        .flatten()
-        .filter_map(get_json)
-        .filter(|e| !e.hidden.unwrap_or(false))
-        .collect();
-    entries.sort_by_key(|e| Reverse(e.created_at));
-    Ok(entries)
+type Loaded = (String, Result<Entry, LoadError>);
+
+fn get_entries(path: impl AsRef<Path>) -> io::Result<Vec<Loaded>> {
+    Ok(read_dir(path)?.flatten().filter_map(get_json).collect())
 }
 
-fn get_json(entry: DirEntry) -> Option<Entry> {
+fn get_json(entry: DirEntry) -> Option<Loaded> {
     let path = entry.path();
-    if path.extension() == Some(OsStr::new("json"))
+    if is_entry_file(&path) {
+        let stem = path.file_stem()?.to_str()?.to_owned();
+        Some((stem, get_entry(path)))
+    } else {
+        None
+    }
+}
+
+fn is_entry_file(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("json"))
         && path
             .file_stem()
             .and_then(OsStr::to_str)
-            .is_some_and(|s| !s.contains('.'))
+            .is_some_and(is_ident)
+}
+
+// Sidecars are files sharing an entry's stem, such as `<id>.live_chat.json`.
+fn sidecar_name(path: &Path) -> Option<(&str, &str)> {
+    let (stem, suffix) = path.file_name()?.to_str()?.split_once('.')?;
+    (is_ident(stem) && suffix != "json" && is_suffix(suffix)).then_some((stem, suffix))
+}
+
+fn is_suffix(s: &str) -> bool {
+    s.len() <= 128 && s.split('.').all(is_ident)
+}
+
+fn is_ident(s: &str) -> bool {
+    (1..=128).contains(&s.len())
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+fn check_kind<'a>(config: &ArchiveConfig, kind: &'a str) -> Result<&'a str, Problem> {
+    if !is_ident(kind) {
+        Err(Problem::new(
+            Status::BadRequest,
+            "invalid-kind",
+            "Invalid kind",
+            format!("{kind:?} is not a valid kind name"),
+        ))
+    } else if !config.contains(kind) {
+        Err(Problem::new(
+            Status::NotFound,
+            "unknown-kind",
+            "Unknown kind",
+            format!("kind {kind:?} is not configured"),
+        ))
+    } else {
+        Ok(kind)
+    }
+}
+
+fn check_id(id: &str) -> Result<&str, Problem> {
+    if is_ident(id) {
+        Ok(id)
+    } else {
+        Err(Problem::new(
+            Status::BadRequest,
+            "invalid-id",
+            "Invalid id",
+            format!("{id:?} is not a valid entry id"),
+        ))
+    }
+}
+
+// Explains why an entry or kind that should exist is missing from the index:
+// its file failed to load, or the kind directory itself could not be read.
+fn load_problem(index: &Index, kind: &str, id: Option<&str>) -> Option<Problem> {
+    let failure = index.failure(kind, id.unwrap_or("."))?;
+    let (slug, title) = match failure.line {
+        Some(_) => ("malformed-entry", "Malformed entry"),
+        None if id.is_none() => ("unreadable-directory", "Unreadable directory"),
+        None => ("unreadable-entry", "Unreadable entry"),
+    };
+    let name = id.map_or(kind.to_owned(), |id| format!("{kind}/{id}"));
+    Some(Problem::new(
+        Status::InternalServerError,
+        slug,
+        title,
+        format!("{name} could not be loaded: {}", failure.error),
+    ))
+}
+
+fn not_found(kind: &str, id: &str) -> Problem {
+    Problem::new(
+        Status::NotFound,
+        "not-found",
+        "Entry not found",
+        format!("there is no entry {kind}/{id}"),
+    )
+}
+
+fn get_entry(path: impl AsRef<Path>) -> Result<Entry, LoadError> {
+    let path = path.as_ref();
+    let result = read_to_string(path)
+        .map_err(|e| LoadError::io(path, e))
+        .and_then(|content| {
+            let mut entry =
+                serde_json::from_str::<Entry>(&content).map_err(|e| LoadError::json(path, e))?;
+            entry.version = etag(content.as_bytes());
+            entry
+                .extra
+                .retain(|k, _| !DERIVED_KEYS.contains(&k.as_str()));
+            if entry.chapters.is_empty() {
+                entry.chapters = chapters::from_description(&entry.description, entry.duration);
+            }
+            Ok(entry)
+        });
+    if let Err(e) = &result {
+        warn!("skipping {e}");
+    }
+    result
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+struct LoadError {
+    path: PathBuf,
+    error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<usize>,
+}
+
+impl LoadError {
+    fn io(path: &Path, e: io::Error) -> Self {
+        LoadError {
+            path: path.to_owned(),
+            error: e.to_string(),
+            line: None,
+            column: None,
+        }
+    }
+
+    fn schema(path: &Path, error: String) -> Self {
+        LoadError {
+            path: path.to_owned(),
+            error,
+            line: None,
+            column: None,
+        }
+    }
+
+    fn json(path: &Path, e: serde_json::Error) -> Self {
+        LoadError {
+            path: path.to_owned(),
+            error: e.to_string(),
+            line: Some(e.line()),
+            column: Some(e.column()),
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, ":{line}:{column}")?;
+        }
+        write!(f, ": {}", self.error)
+    }
+}
+
+fn validate() -> i32 {
+    let config = match rocket::Config::figment()
+        .focus("archive")
+        .extract::<ArchiveConfig>()
     {
-        get_entry(path)
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("invalid archive configuration: {e}");
+            return 2;
+        }
+    };
+    if let Err(e) = config.check() {
+        eprintln!("invalid archive configuration: {e}");
+        return 2;
+    }
+    let index = Index::load(&config);
+    let failures: Vec<_> = index
+        .failures()
+        .into_iter()
+        .chain(index.dangling())
+        .collect();
+    for (_, e) in &failures {
+        println!("{e}");
+    }
+    let failures = failures.len();
+    if failures > 0 {
+        eprintln!("{failures} problem(s) found");
+        1
+    } else {
+        0
+    }
+}
+
+#[get("/")]
+fn index(config: &State<ArchiveConfig>) -> Json<Vec<KindInfo<'_>>> {
+    Json(config.kinds())
+}
+
+#[get("/admin/failures")]
+fn failures(index: &State<Index>, _auth: Auth) -> Json<Vec<Failure>> {
+    Json(
+        index
+            .failures()
+            .into_iter()
+            .chain(index.dangling())
+            .map(|(kind, error)| Failure { kind, error })
+            .collect(),
+    )
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+struct Failure {
+    kind: String,
+    #[serde(flatten)]
+    error: LoadError,
+}
+
+#[get("/status")]
+fn status(index: &State<Index>) -> Json<IndexStats> {
+    Json(index.stats())
+}
+
+// Prometheus text exposition; archive gauges are computed from the index on scrape.
+#[get("/metrics")]
+fn scrape(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    metrics: &State<Metrics>,
+) -> (ContentType, String) {
+    let now = Utc::now();
+    let kinds: Vec<_> = config
+        .kinds()
+        .into_iter()
+        .map(|k| (k.id, index.list(k.id).unwrap_or_default()))
+        .collect();
+    let failures = index.failures();
+    let mut out = String::new();
+    metrics::gauge(
+        &mut out,
+        "kgg_entries",
+        "Entries loaded per kind.",
+        kinds.iter().map(|(kind, e)| (*kind, e.len() as f64)),
+    );
+    metrics::gauge(
+        &mut out,
+        "kgg_hidden_entries",
+        "Entries that are not currently public.",
+        kinds.iter().map(|(kind, entries)| {
+            let hidden = entries
+                .iter()
+                .filter(|e| e.effective_visibility(now) != Visibility::Public)
+                .count();
+            (*kind, hidden as f64)
+        }),
+    );
+    metrics::gauge(
+        &mut out,
+        "kgg_malformed_files",
+        "Entry files that failed to load.",
+        kinds.iter().map(|(kind, _)| {
+            let count = failures.iter().filter(|(k, _)| k == kind).count();
+            (*kind, count as f64)
+        }),
+    );
+    metrics::gauge(
+        &mut out,
+        "kgg_archived_duration_seconds",
+        "Total duration of all entries.",
+        kinds.iter().map(|(kind, entries)| {
+            let total = entries
+                .iter()
+                .fold(0.0, |t, e| t + e.duration.as_secs_f64());
+            (*kind, total)
+        }),
+    );
+    let scans = index.scan_durations();
+    metrics::gauge(
+        &mut out,
+        "kgg_scan_duration_seconds",
+        "Duration of the last full directory scan.",
+        kinds.iter().filter_map(|(kind, _)| {
+            let (_, scan) = scans.iter().find(|(k, _)| k == kind)?;
+            Some((*kind, scan.as_secs_f64()))
+        }),
+    );
+    metrics.render(&mut out);
+    let content_type = ContentType::new("text", "plain").with_params(("version", "0.0.4"));
+    (content_type, out)
+}
+
+#[get("/search?<q>&<kind>&<limit>")]
+fn search(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    auth: Option<Auth>,
+    q: Option<&str>,
+    kind: Option<&str>,
+    limit: Option<usize>,
+) -> Result<Json<Vec<Hit>>, Problem> {
+    let query = parse_query(q.ok_or_else(|| missing_param("q is required"))?)?;
+    let now = Utc::now();
+    let kinds = match kind {
+        Some(kind) => vec![check_kind(config, kind)?],
+        None => config.kinds().into_iter().map(|k| k.id).collect(),
+    };
+    let mut hits: Vec<_> = kinds
+        .into_iter()
+        .filter_map(|kind| Some((kind, index.list(kind)?)))
+        .flat_map(|(kind, entries)| {
+            entries
+                .iter()
+                .filter(|e| e.is_listed(auth.is_some(), now))
+                .filter_map(|e| query.hit(kind, e))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    hits.truncate(limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT));
+    Ok(Json(
+        hits.into_iter()
+            .map(|h| Hit {
+                entry: h.entry.redact(config, auth.is_some()),
+                ..h
+            })
+            .collect(),
+    ))
+}
+
+fn chat_logs(
+    config: &ArchiveConfig,
+    index: &Index,
+    filter: impl Fn(&Entry) -> bool,
+) -> Vec<ChatLog> {
+    let mut logs = Vec::new();
+    for kind in config.kinds() {
+        for entry in index.list(kind.id).iter().flat_map(|e| e.iter()) {
+            if !filter(entry) || !entry.sidecars.iter().any(|s| s.suffix == CHAT_SUFFIX) {
+                continue;
+            }
+            if let Some(path) = index.sidecar_path(kind.id, &entry.id, CHAT_SUFFIX) {
+                logs.push(ChatLog {
+                    kind: kind.id.to_owned(),
+                    id: entry.id.clone(),
+                    path,
+                });
+            }
+        }
+    }
+    logs
+}
+
+#[get("/chat/search?<q>&<author>&<limit>")]
+async fn chat_search(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    search: &State<ChatSearch>,
+    auth: Option<Auth>,
+    q: Option<&str>,
+    author: Option<&str>,
+    limit: Option<usize>,
+) -> Result<Json<Vec<ChatHit>>, Problem> {
+    let terms: Vec<_> = fulltext::tokenize(q.unwrap_or_default())
+        .into_iter()
+        .map(|(_, t)| t)
+        .collect();
+    let author = author.map(str::trim).filter(|a| !a.is_empty());
+    if terms.is_empty() && author.is_none() {
+        return Err(match q {
+            Some(q) => invalid_query(q),
+            None => missing_param("q or author is required"),
+        });
+    }
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let now = Utc::now();
+    let logs = chat_logs(config, index, |e| e.is_listed(auth.is_some(), now));
+    let search = search.inner().clone();
+    let author = author.map(str::to_owned);
+    // Cold logs are indexed on first search, which reads the whole log.
+    let found = rocket::tokio::task::spawn_blocking(move || {
+        let mut found = Vec::new();
+        for log in logs {
+            let left = limit - found.len();
+            match search.search(&log, &terms, author.as_deref(), left) {
+                Ok(messages) => found.extend(messages.into_iter().map(|m| (log.clone(), m))),
+                Err(e) => warn!("cannot search chat {}: {e}", log.path.display()),
+            }
+            if found.len() >= limit {
+                break;
+            }
+        }
+        found
+    })
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+    let hits = found
+        .into_iter()
+        .map(|(log, message)| {
+            let from = format!("{}ms", message.offset_ms);
+            let link =
+                uri!("/api", entry_chat(&log.kind, &log.id, Some(from), _, _, _)).to_string();
+            ChatHit {
+                kind: log.kind,
+                id: log.id,
+                link,
+                message,
+            }
+        })
+        .collect();
+    Ok(Json(hits))
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+struct ChatHit {
+    kind: String,
+    id: String,
+    link: String,
+    message: Message,
+}
+
+#[get("/transcripts/search?<q>&<kind>&<limit>")]
+async fn transcript_search(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    subtitles: &State<SubtitleStore>,
+    auth: Option<Auth>,
+    q: Option<&str>,
+    kind: Option<&str>,
+    limit: Option<usize>,
+) -> Result<Json<Vec<TranscriptHit>>, Problem> {
+    let query = parse_query(q.ok_or_else(|| missing_param("q is required"))?)?;
+    let now = Utc::now();
+    let kinds = match kind {
+        Some(kind) => vec![check_kind(config, kind)?],
+        None => config.kinds().into_iter().map(|k| k.id).collect(),
+    };
+    let mut transcripts = Vec::new();
+    for kind in kinds {
+        for entry in index.list(kind).iter().flat_map(|e| e.iter()) {
+            if !entry.is_listed(auth.is_some(), now) {
+                continue;
+            }
+            for suffix in entry.sidecars.iter().map(|s| s.suffix.as_str()) {
+                if Format::of_suffix(suffix).is_none() {
+                    continue;
+                }
+                if let Some(path) = index.sidecar_path(kind, &entry.id, suffix) {
+                    transcripts.push(Transcript {
+                        kind: kind.to_owned(),
+                        id: entry.id.clone(),
+                        suffix: suffix.to_owned(),
+                        duration_ms: entry.duration.as_millis() as u64,
+                        path,
+                    });
+                }
+            }
+        }
+    }
+    let subtitles = subtitles.inner().clone();
+    // Files not searched before are read and parsed in full.
+    let mut hits = rocket::tokio::task::spawn_blocking(move || {
+        let mut hits = Vec::new();
+        for transcript in transcripts {
+            match subtitles.cues(&transcript.path) {
+                Ok(cues) => hits.extend(transcript.hits(&query, &cues)),
+                Err(e) => warn!("cannot read subtitles {}: {e}", transcript.path.display()),
+            }
+        }
+        hits
+    })
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    hits.truncate(limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT));
+    Ok(Json(hits))
+}
+
+struct Transcript {
+    kind: String,
+    id: String,
+    suffix: String,
+    duration_ms: u64,
+    path: PathBuf,
+}
+
+impl Transcript {
+    fn hits(&self, query: &Query, cues: &[subtitle::Cue]) -> Vec<TranscriptHit> {
+        let duration_ms = self.duration_ms;
+        let media = uri!("/api", entry_media(&self.kind, &self.id));
+        // Cues past the end of the recording cannot be jumped to.
+        cues.iter()
+            .filter(|c| duration_ms == 0 || c.start_ms < duration_ms)
+            .filter_map(|cue| {
+                let (score, snippet) = query.text_hit(&cue.plain_text())?;
+                let end_ms = match duration_ms {
+                    0 => cue.end_ms,
+                    d => cue.end_ms.min(d),
+                };
+                Some(TranscriptHit {
+                    kind: self.kind.clone(),
+                    id: self.id.clone(),
+                    suffix: self.suffix.clone(),
+                    start_ms: cue.start_ms,
+                    end_ms,
+                    score,
+                    snippet,
+                    link: format!("{media}#t={:.3}", cue.start_ms as f64 / 1000.0),
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+struct TranscriptHit {
+    kind: String,
+    id: String,
+    suffix: String,
+    start_ms: u64,
+    end_ms: u64,
+    score: f64,
+    snippet: String,
+    link: String,
+}
+
+#[get("/songs?<artist>&<q>&<limit>")]
+fn song_index(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    auth: Option<Auth>,
+    artist: Option<&str>,
+    q: Option<&str>,
+    limit: Option<usize>,
+) -> Result<Json<Vec<SongSummary>>, Problem> {
+    let query = q.map(parse_query).transpose()?;
+    let artist = artist.map(str::trim);
+    let now = Utc::now();
+    let lists: Vec<_> = config
+        .kinds()
+        .into_iter()
+        .filter_map(|kind| Some((kind.id, index.list(kind.id)?)))
+        .collect();
+    let entries = lists.iter().flat_map(|(kind, entries)| {
+        entries
+            .iter()
+            .filter(|e| e.is_listed(auth.is_some(), now))
+            .map(move |e| (*kind, e))
+    });
+    let mut songs = songs::summarize(entries, |song| {
+        artist.is_none_or(|artist| {
+            song.artist
+                .as_deref()
+                .is_some_and(|a| a.trim().eq_ignore_ascii_case(artist))
+        }) && query
+            .as_ref()
+            .is_none_or(|q| q.text_hit(&song.title).is_some())
+    });
+    songs.truncate(limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT));
+    Ok(Json(songs))
+}
+
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+
+#[allow(clippy::too_many_arguments)]
+#[get("/<kind>?<limit>&<cursor>&<after>&<before>&<min_duration>&<max_duration>")]
+fn lists(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    auth: Option<Auth>,
+    pre: Preconditions<'_>,
+    kind: &str,
+    limit: Option<usize>,
+    cursor: Option<&str>,
+    after: Option<&str>,
+    before: Option<&str>,
+    min_duration: Option<&str>,
+    max_duration: Option<&str>,
+) -> Result<Tagged<Json<Page>>, Problem> {
+    check_kind(config, kind)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let cursor = cursor
+        .map(|c| Cursor::decode(c).ok_or_else(|| invalid_cursor(c)))
+        .transpose()?;
+    let filter = Filter {
+        after: parse_param(after, parse_timestamp)?,
+        before: parse_param(before, parse_timestamp)?,
+        min_duration: parse_param(min_duration, parse_duration_str)?,
+        max_duration: parse_param(max_duration, parse_duration_str)?,
+    };
+    if let Some(problem) = load_problem(index, kind, None) {
+        return Err(problem);
+    }
+    let now = Utc::now();
+    let mut entries: Vec<_> = index
+        .list(kind)
+        .ok_or(Status::NotFound)?
+        .iter()
+        .filter(|e| e.is_listed(auth.is_some(), now) && filter.matches(e))
+        .skip_while(|e| {
+            cursor
+                .as_ref()
+                .is_some_and(|c| e.sort_key() >= (c.created_at, c.id.as_str()))
+        })
+        .take(limit + 1)
+        .map(|e| e.clone().redact(config, auth.is_some()))
+        .collect();
+    let next = if entries.len() > limit {
+        entries.truncate(limit);
+        entries.last().map(|e| {
+            let cursor = Cursor::of(e).encode();
+            uri!(
+                "/api",
+                lists(
+                    kind,
+                    Some(limit),
+                    Some(cursor),
+                    after,
+                    before,
+                    min_duration,
+                    max_duration
+                )
+            )
+            .to_string()
+        })
     } else {
         None
+    };
+    let page = Page { entries, next };
+    let etag = etag(&serde_json::to_vec(&page).map_err(|_| Status::InternalServerError)?);
+    Ok(Tagged::new(etag, Json(page), &pre))
+}
+
+struct Filter {
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+    min_duration: Option<Duration>,
+    max_duration: Option<Duration>,
+}
+
+impl Filter {
+    // `after` is inclusive and `before` exclusive, so consecutive ranges never overlap.
+    fn matches(&self, entry: &Entry) -> bool {
+        self.after.is_none_or(|t| entry.created_at >= t)
+            && self.before.is_none_or(|t| entry.created_at < t)
+            && self.min_duration.is_none_or(|d| entry.duration >= d)
+            && self.max_duration.is_none_or(|d| entry.duration <= d)
     }
 }
 
-fn get_entry(path: impl AsRef<Path>) -> Option<Entry> {
-    serde_json::from_str::<Entry>(&read_to_string(path).ok()?).ok()
+fn parse_param<T>(value: Option<&str>, parse: fn(&str) -> Option<T>) -> Result<Option<T>, Problem> {
+    value
+        .map(|v| parse(v).ok_or_else(|| invalid_param(format!("{v:?} is not a valid value"))))
+        .transpose()
 }
 
-#[get("/")]
-fn index() -> Json<[&'static str; 4]> {
-    Json(["vods", "highlights", "clips", "rplay"])
+fn invalid_param(detail: impl Into<String>) -> Problem {
+    Problem::new(
+        Status::BadRequest,
+        "invalid-parameter",
+        "Invalid parameter",
+        detail,
+    )
+}
+
+fn missing_param(detail: &str) -> Problem {
+    Problem::new(
+        Status::BadRequest,
+        "missing-parameter",
+        "Missing parameter",
+        detail,
+    )
+}
+
+fn invalid_cursor(cursor: &str) -> Problem {
+    Problem::new(
+        Status::BadRequest,
+        "invalid-cursor",
+        "Invalid cursor",
+        format!("{cursor:?} is not a cursor from a previous page"),
+    )
+}
+
+// Punctuation alone matches nothing, so a query must contain at least one word.
+fn parse_query(q: &str) -> Result<Query, Problem> {
+    Query::parse(q).ok_or_else(|| invalid_query(q))
+}
+
+fn invalid_query(q: &str) -> Problem {
+    Problem::new(
+        Status::BadRequest,
+        "invalid-query",
+        "Invalid query",
+        format!("{q:?} contains no words to search for"),
+    )
+}
+
+fn parse_timestamp(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|t| t.to_utc())
+        .or_else(|_| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d").map(|d| d.and_time(NaiveTime::MIN).and_utc())
+        })
+        .ok()
+}
+
+fn parse_duration_str(s: &str) -> Option<Duration> {
+    match s.parse::<f64>() {
+        Ok(secs) => Duration::try_from_secs_f64(secs).ok(),
+        Err(_) => parse_duration(s).ok(),
+    }
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+struct Page {
+    entries: Vec<Entry>,
+    next: Option<String>,
+}
+
+struct Cursor {
+    created_at: DateTime<Utc>,
+    id: String,
+}
+
+impl Cursor {
+    fn of(entry: &Entry) -> Self {
+        Cursor {
+            created_at: entry.created_at,
+            id: entry.id.clone(),
+        }
+    }
+
+    fn encode(&self) -> String {
+        let raw = format!(
+            "{}|{}",
+            self.created_at.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            self.id
+        );
+        URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    fn decode(s: &str) -> Option<Self> {
+        let raw = String::from_utf8(URL_SAFE_NO_PAD.decode(s).ok()?).ok()?;
+        let (created_at, id) = raw.split_once('|')?;
+        Some(Cursor {
+            created_at: DateTime::parse_from_rfc3339(created_at).ok()?.to_utc(),
+            id: id.to_owned(),
+        })
+    }
 }
 
-#[get("/<kind>")]
-fn lists(kind: &str) -> Result<Json<Vec<Entry>>, Status> {
-    get_entries(kind).map(Json)
+fn feed_entries(
+    config: &ArchiveConfig,
+    index: &Index,
+    authorized: bool,
+    kind: &str,
+    keep: impl Fn(&Entry) -> bool,
+) -> Result<Vec<Entry>, Problem> {
+    let now = Utc::now();
+    Ok(index
+        .list(kind)
+        .ok_or(Status::NotFound)?
+        .iter()
+        .filter(|e| e.is_listed(authorized, now) && keep(e))
+        .take(DEFAULT_LIMIT)
+        .map(|e| e.clone().redact(config, authorized))
+        .collect())
+}
+
+fn make_feed<'a>(
+    config: &'a ArchiveConfig,
+    base: &BaseUrl,
+    kind: &'a str,
+    name: &str,
+    entries: &'a [Entry],
+) -> Feed<'a> {
+    let base = &base.0;
+    Feed {
+        title: config.name(kind).unwrap_or(kind),
+        link: format!("{base}{}", uri!("/api", lists(kind, _, _, _, _, _, _))),
+        self_url: format!("{base}/api/{kind}/{name}"),
+        items: entries
+            .iter()
+            .map(|entry| feed::Item {
+                url: format!("{base}{}", uri!("/api", entry(kind, &entry.id))),
+                entry,
+                enclosure: None,
+                chapters_url: None,
+            })
+            .collect(),
+    }
+}
+
+fn tagged_feed(
+    body: String,
+    content_type: &str,
+    pre: &Preconditions,
+) -> Result<Tagged<(ContentType, String)>, Problem> {
+    let content_type =
+        ContentType::parse_flexible(content_type).ok_or(Status::InternalServerError)?;
+    Ok(Tagged::new(
+        etag(body.as_bytes()),
+        (content_type, body),
+        pre,
+    ))
+}
+
+#[get("/<kind>/feed.rss")]
+fn rss_feed(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    auth: Option<Auth>,
+    pre: Preconditions<'_>,
+    base: BaseUrl,
+    kind: &str,
+) -> Result<Tagged<(ContentType, String)>, Problem> {
+    let kind = check_kind(config, kind)?;
+    let entries = feed_entries(config, index, auth.is_some(), kind, |_| true)?;
+    let feed = make_feed(config, &base, kind, "feed.rss", &entries);
+    tagged_feed(feed::rss(&feed), "application/rss+xml", &pre)
+}
+
+#[get("/<kind>/feed.atom")]
+fn atom_feed(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    auth: Option<Auth>,
+    pre: Preconditions<'_>,
+    base: BaseUrl,
+    kind: &str,
+) -> Result<Tagged<(ContentType, String)>, Problem> {
+    let kind = check_kind(config, kind)?;
+    let entries = feed_entries(config, index, auth.is_some(), kind, |_| true)?;
+    let feed = make_feed(config, &base, kind, "feed.atom", &entries);
+    tagged_feed(feed::atom(&feed), "application/atom+xml", &pre)
+}
+
+#[get("/<kind>/feed.json")]
+fn json_feed(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    auth: Option<Auth>,
+    pre: Preconditions<'_>,
+    base: BaseUrl,
+    kind: &str,
+) -> Result<Tagged<(ContentType, String)>, Problem> {
+    let kind = check_kind(config, kind)?;
+    let entries = feed_entries(config, index, auth.is_some(), kind, |_| true)?;
+    let feed = make_feed(config, &base, kind, "feed.json", &entries);
+    tagged_feed(feed::json(&feed).to_string(), "application/feed+json", &pre)
+}
+
+#[get("/<kind>/podcast.rss")]
+fn podcast_feed(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    auth: Option<Auth>,
+    pre: Preconditions<'_>,
+    base: BaseUrl,
+    kind: &str,
+) -> Result<Tagged<(ContentType, String)>, Problem> {
+    let kind = check_kind(config, kind)?;
+    // Entries without media are filtered out before the item limit is applied, so
+    // older playable episodes still fill the feed.
+    let has_media = |e: &Entry| {
+        index
+            .path(kind, &e.id)
+            .and_then(|p| find_media(&p))
+            .is_some()
+    };
+    let entries = feed_entries(config, index, auth.is_some(), kind, has_media)?;
+    let mut feed = make_feed(config, &base, kind, "podcast.rss", &entries);
+    for item in &mut feed.items {
+        let id = item.entry.id.as_str();
+        let media = index.path(kind, id).and_then(|p| find_media(&p));
+        item.enclosure = media.and_then(|(path, mime)| {
+            Some(feed::Enclosure {
+                url: format!("{}{}", base.0, uri!("/api", entry_media(kind, id))),
+                length: std::fs::metadata(path).ok()?.len(),
+                mime,
+            })
+        });
+        if !item.entry.chapters.is_empty() {
+            let chapters = uri!("/api", entry_chapters(kind, id, Some("json")));
+            item.chapters_url = Some(format!("{}{chapters}", base.0));
+        }
+    }
+    let image = config.podcast.image.as_ref().map(|image| {
+        if image.starts_with('/') {
+            format!("{}{image}", base.0)
+        } else {
+            image.clone()
+        }
+    });
+    let podcast = feed::podcast(&feed, &config.podcast, image.as_deref());
+    tagged_feed(podcast, "application/rss+xml", &pre)
 }
 
 #[get("/<kind>/<id>")]
-fn entry(kind: &str, id: &str) -> Result<Json<Entry>, Status> {
-    let path = PathBuf::from(kind).join(id).with_extension("json");
-    if path.exists() {
-        get_entry(path).ok_or(Status::InternalServerError).map(Json)
-    } else {
-        Err(Status::NotFound)
+fn entry(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    auth: Option<Auth>,
+    pre: Preconditions<'_>,
+    kind: &str,
+    id: &str,
+) -> Result<Tagged<Versioned<Json<EntryView>>>, Problem> {
+    let (kind, id) = (check_kind(config, kind)?, check_id(id)?);
+    let now = Utc::now();
+    let entry = match index.get(kind, id) {
+        Some(entry) if entry.is_reachable(auth.is_some(), now) => entry,
+        Some(_) => return Err(not_found(kind, id)),
+        None => {
+            return Err(load_problem(index, kind, Some(id)).unwrap_or_else(|| not_found(kind, id)));
+        }
     }
+    .redact(config, auth.is_some());
+    let vod = config
+        .parent(kind)
+        .zip(entry.vod_id.as_deref())
+        .and_then(|(parent, vod_id)| index.get(parent, vod_id))
+        .filter(|e| e.is_reachable(auth.is_some(), now))
+        .map(|e| e.redact(config, auth.is_some()));
+    // The body embeds sidecars and the parent entry, so it is tagged by its own
+    // hash; the file version that writes check is sent as `Entry-Version`.
+    let version = entry.version.clone();
+    let view = EntryView { entry, vod };
+    let etag = etag(&serde_json::to_vec(&view).map_err(|_| Status::InternalServerError)?);
+    Ok(Tagged::new(etag, Versioned(version, Json(view)), &pre))
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+struct EntryView {
+    #[serde(flatten)]
+    entry: Entry,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vod: Option<Entry>,
+}
+
+#[get("/<kind>/<id>/media")]
+async fn entry_media(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    auth: Option<Auth>,
+    range: RangeHeader<'_>,
+    kind: &str,
+    id: &str,
+) -> Result<Media, Problem> {
+    let (kind, id) = (check_kind(config, kind)?, check_id(id)?);
+    index
+        .get(kind, id)
+        .filter(|e| e.is_reachable(auth.is_some(), Utc::now()))
+        .ok_or(Status::NotFound)?;
+    let path = index.path(kind, id).ok_or(Status::NotFound)?;
+    let (path, mime) = find_media(&path).ok_or(Status::NotFound)?;
+    Ok(Media::open(&path, mime, &range).await?)
+}
+
+#[allow(clippy::too_many_arguments)]
+#[get("/<kind>/<id>/files/<suffix>?<format>")]
+async fn sidecar(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    subtitles: &State<SubtitleStore>,
+    auth: Option<Auth>,
+    range: RangeHeader<'_>,
+    kind: &str,
+    id: &str,
+    suffix: &str,
+    format: Option<&str>,
+) -> Result<Either<Media, (ContentType, String)>, Problem> {
+    let (kind, id) = (check_kind(config, kind)?, check_id(id)?);
+    if !is_suffix(suffix) || suffix == "json" {
+        return Err(Problem::new(
+            Status::BadRequest,
+            "invalid-suffix",
+            "Invalid suffix",
+            format!("{suffix:?} is not a sidecar suffix"),
+        ));
+    }
+    index
+        .get(kind, id)
+        .filter(|e| e.is_reachable(auth.is_some(), Utc::now()))
+        .ok_or(Status::NotFound)?;
+    let path = index
+        .sidecar_path(kind, id, suffix)
+        .ok_or(Status::NotFound)?;
+    let Some(format) = format else {
+        return Ok(Either::Left(
+            Media::open(&path, content_type(suffix), &range).await?,
+        ));
+    };
+    let format = Format::parse(format)
+        .ok_or_else(|| invalid_param(format!("{format:?} is not a subtitle format")))?;
+    if Format::of_suffix(suffix).is_none() {
+        return Err(invalid_param(format!("{suffix} is not a subtitle file")));
+    }
+    let subtitles = subtitles.inner().clone();
+    let cues = rocket::tokio::task::spawn_blocking(move || subtitles.cues(&path))
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map_err(io_status)?;
+    let content_type =
+        ContentType::parse_flexible(format.content_type()).unwrap_or(ContentType::Text);
+    Ok(Either::Right((
+        content_type,
+        subtitle::render(&cues, format),
+    )))
+}
+
+#[get("/<kind>/<id>/chapters?<format>")]
+fn entry_chapters(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    auth: Option<Auth>,
+    kind: &str,
+    id: &str,
+    format: Option<&str>,
+) -> Result<(ContentType, String), Problem> {
+    let (kind, id) = (check_kind(config, kind)?, check_id(id)?);
+    let entry = index
+        .get(kind, id)
+        .filter(|e| e.is_reachable(auth.is_some(), Utc::now()))
+        .ok_or(Status::NotFound)?;
+    match format.unwrap_or("vtt") {
+        "json" => Ok((
+            ContentType::new("application", "json+chapters"),
+            chapters::json(&entry.chapters).to_string(),
+        )),
+        "ffmetadata" => Ok((
+            ContentType::Plain,
+            chapters::ffmetadata(&entry.title, &entry.chapters, entry.duration),
+        )),
+        format => {
+            let format = Format::parse(format)
+                .ok_or_else(|| invalid_param(format!("{format:?} is not a chapter format")))?;
+            let cues = chapters::cues(&entry.chapters, entry.duration);
+            let content_type =
+                ContentType::parse_flexible(format.content_type()).unwrap_or(ContentType::Text);
+            Ok((content_type, subtitle::render(&cues, format)))
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+#[get("/<kind>/<id>/chat?<from>&<to>&<limit>&<cursor>")]
+async fn entry_chat(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    chats: &State<ChatStore>,
+    auth: Option<Auth>,
+    kind: &str,
+    id: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+    limit: Option<usize>,
+    cursor: Option<&str>,
+) -> Result<Json<ChatPage>, Problem> {
+    let (kind, id) = (check_kind(config, kind)?, check_id(id)?);
+    let from_ms = parse_param(from, parse_duration_str)?.map_or(0, |d| d.as_millis() as u64);
+    let to_ms = parse_param(to, parse_duration_str)?.map(|d| d.as_millis() as u64);
+    let position = cursor
+        .map(|c| {
+            let raw = URL_SAFE_NO_PAD.decode(c).ok();
+            let position = raw.and_then(|r| String::from_utf8(r).ok()?.parse::<u64>().ok());
+            position.ok_or_else(|| invalid_cursor(c))
+        })
+        .transpose()?;
+    let limit = limit.unwrap_or(MAX_LIMIT).clamp(1, MAX_LIMIT);
+    index
+        .get(kind, id)
+        .filter(|e| e.is_reachable(auth.is_some(), Utc::now()))
+        .ok_or(Status::NotFound)?;
+    let path = index
+        .sidecar_path(kind, id, CHAT_SUFFIX)
+        .ok_or(Status::NotFound)?;
+    // The first request for a log indexes all of it.
+    let chats = chats.inner().clone();
+    let (total, window) = rocket::tokio::task::spawn_blocking(move || {
+        let total = chats.index(&path)?.messages;
+        Ok((total, chats.window(&path, from_ms, to_ms, position, limit)?))
+    })
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .map_err(io_status)?;
+    let next = window.next.map(|pos| {
+        let cursor = URL_SAFE_NO_PAD.encode(pos.to_string());
+        uri!(
+            "/api",
+            entry_chat(kind, id, from, to, Some(limit), Some(cursor))
+        )
+        .to_string()
+    });
+    Ok(Json(ChatPage {
+        total,
+        messages: window.messages,
+        next,
+    }))
+}
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(30);
+const DEFAULT_THRESHOLD: f64 = 2.0;
+
+#[allow(clippy::too_many_arguments)]
+#[get("/<kind>/<id>/chat/activity?<interval>&<threshold>&<limit>")]
+async fn chat_activity(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    auth: Option<Auth>,
+    kind: &str,
+    id: &str,
+    interval: Option<&str>,
+    threshold: Option<&str>,
+    limit: Option<usize>,
+) -> Result<Json<Activity>, Problem> {
+    let (kind, id) = (check_kind(config, kind)?, check_id(id)?);
+    let interval = parse_param(interval, parse_duration_str)?.unwrap_or(DEFAULT_INTERVAL);
+    if interval < Duration::from_secs(1) {
+        return Err(invalid_param("interval must be at least 1s"));
+    }
+    let threshold = parse_param(threshold, |s| {
+        s.parse().ok().filter(|t: &f64| t.is_finite())
+    })?
+    .unwrap_or(DEFAULT_THRESHOLD);
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    index
+        .get(kind, id)
+        .filter(|e| e.is_reachable(auth.is_some(), Utc::now()))
+        .ok_or(Status::NotFound)?;
+    let path = index
+        .sidecar_path(kind, id, CHAT_SUFFIX)
+        .ok_or(Status::NotFound)?;
+    // Logs run to gigabytes, so the scan must not tie up an async worker.
+    let interval_ms = interval.as_millis() as u64;
+    rocket::tokio::task::spawn_blocking(move || {
+        activity::analyze(&path, interval_ms, threshold, limit)
+    })
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .map(Json)
+    .map_err(io_status)
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+struct ChatPage {
+    total: usize,
+    messages: Vec<Message>,
+    next: Option<String>,
+}
+
+#[get("/<kind>/<id>/<children>")]
+fn children(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    auth: Option<Auth>,
+    kind: &str,
+    id: &str,
+    children: &str,
+) -> Result<Json<Vec<Entry>>, Problem> {
+    let (kind, id) = (check_kind(config, kind)?, check_id(id)?);
+    let children = check_kind(config, children)?;
+    if config.parent(children) != Some(kind) {
+        return Err(Status::NotFound.into());
+    }
+    let now = Utc::now();
+    index
+        .get(kind, id)
+        .filter(|e| e.is_reachable(auth.is_some(), now))
+        .ok_or(Status::NotFound)?;
+    let mut entries: Vec<_> = index
+        .list(children)
+        .ok_or(Status::NotFound)?
+        .iter()
+        .filter(|e| e.vod_id.as_deref() == Some(id) && e.is_listed(auth.is_some(), now))
+        .map(|e| e.clone().redact(config, auth.is_some()))
+        .collect();
+    entries.sort_by_key(|e| e.start_offset);
+    Ok(Json(entries))
+}
+
+#[post("/<kind>", format = "json", data = "<body>")]
+fn create_entry(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    _auth: Auth,
+    kind: &str,
+    mut body: Json<Value>,
+) -> Result<Versioned<Created<Json<Entry>>>, Problem> {
+    let kind = check_kind(config, kind)?;
+    let entry = parse_entry(config, kind, &mut body)?;
+    let _lock = index.lock_writes();
+    let version = index
+        .write(kind, check_id(&entry.id)?, &body, true)
+        .map_err(io_status)?;
+    let location = uri!("/api", entry(kind, &entry.id)).to_string();
+    Ok(Versioned(version, Created::new(location).body(Json(entry))))
+}
+
+#[put("/<kind>/<id>", format = "json", data = "<body>")]
+fn replace_entry(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    _auth: Auth,
+    pre: Preconditions<'_>,
+    kind: &str,
+    id: &str,
+    mut body: Json<Value>,
+) -> Result<Versioned<Json<Entry>>, Problem> {
+    let (kind, id) = (check_kind(config, kind)?, check_id(id)?);
+    let entry = parse_entry(config, kind, &mut body)?;
+    if entry.id != id {
+        return Err(Status::UnprocessableEntity.into());
+    }
+    let _lock = index.lock_writes();
+    let current = index.current_version(kind, id).map_err(io_status)?;
+    pre.check(&current)?;
+    let version = index.write(kind, id, &body, false).map_err(io_status)?;
+    Ok(Versioned(version, Json(entry)))
+}
+
+#[patch("/<kind>/<id>", format = "json", data = "<patch>")]
+fn update_entry(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    _auth: Auth,
+    pre: Preconditions<'_>,
+    kind: &str,
+    id: &str,
+    patch: Json<Value>,
+) -> Result<Versioned<Json<Entry>>, Problem> {
+    let (kind, id) = (check_kind(config, kind)?, check_id(id)?);
+    let _lock = index.lock_writes();
+    let (mut value, current) = index.read_raw(kind, id).map_err(io_status)?;
+    pre.check(&current)?;
+    merge_patch(&mut value, &patch);
+    let entry = parse_entry(config, kind, &mut value)?;
+    if entry.id != id {
+        return Err(Status::UnprocessableEntity.into());
+    }
+    let version = index.write(kind, id, &value, false).map_err(io_status)?;
+    Ok(Versioned(version, Json(entry)))
+}
+
+#[delete("/<kind>/<id>")]
+fn delete_entry(
+    config: &State<ArchiveConfig>,
+    index: &State<Index>,
+    _auth: Auth,
+    pre: Preconditions<'_>,
+    kind: &str,
+    id: &str,
+) -> Result<NoContent, Problem> {
+    let (kind, id) = (check_kind(config, kind)?, check_id(id)?);
+    let _lock = index.lock_writes();
+    let current = index.current_version(kind, id).map_err(io_status)?;
+    pre.check(&current)?;
+    index.delete(kind, id).map_err(io_status)?;
+    Ok(NoContent)
+}
+
+// Drops the keys the server adds to responses, so that a fetched entry can be
+// written back as is.
+fn parse_entry(config: &ArchiveConfig, kind: &str, value: &mut Value) -> Result<Entry, Problem> {
+    let invalid = |detail: String| {
+        Problem::new(
+            Status::UnprocessableEntity,
+            "invalid-entry",
+            "Invalid entry",
+            detail,
+        )
+    };
+    if let Value::Object(map) = value {
+        map.retain(|k, _| !DERIVED_KEYS.contains(&k.as_str()));
+    }
+    let entry: Entry = serde_json::from_value(value.clone()).map_err(|e| invalid(e.to_string()))?;
+    entry
+        .check_schema(config.parent(kind).is_some())
+        .map_err(invalid)?;
+    Ok(entry)
+}
+
+// JSON Merge Patch (RFC 7396): objects merge recursively, `null` removes a key.
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(target.entry(key).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+fn io_status(e: io::Error) -> Problem {
+    match e.kind() {
+        io::ErrorKind::NotFound => Status::NotFound.into(),
+        io::ErrorKind::AlreadyExists => Problem::new(
+            Status::Conflict,
+            "already-exists",
+            "Entry already exists",
+            "an entry with this id already exists",
+        ),
+        _ => {
+            error!("entry I/O failed: {e}");
+            Problem::new(
+                Status::InternalServerError,
+                "io-error",
+                "Storage error",
+                e.to_string(),
+            )
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(crate = "rocket::serde")]
 struct Entry {
     id: String,
@@ -78,9 +1493,122 @@ struct Entry {
         deserialize_with = "parse_duration_flex",
         serialize_with = "serialize_duration"
     )]
+    #[schemars(schema_with = "openapi::duration_schema")]
     duration: Duration,
     #[serde(skip_serializing_if = "Option::is_none")]
     hidden: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    visibility: Option<Visibility>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    publish_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    vod_id: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "parse_duration_flex_opt",
+        serialize_with = "serialize_duration_opt",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(schema_with = "openapi::duration_schema")]
+    start_offset: Option<Duration>,
+    #[serde(
+        default,
+        deserialize_with = "parse_duration_flex_opt",
+        serialize_with = "serialize_duration_opt",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(schema_with = "openapi::duration_schema")]
+    end_offset: Option<Duration>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    chapters: Vec<Chapter>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    setlist: Vec<Song>,
+    #[serde(skip_deserializing, skip_serializing_if = "Vec::is_empty")]
+    sidecars: Vec<Sidecar>,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+    #[serde(skip)]
+    version: String,
+}
+
+// Keys that only the server fills in. Stored under `extra` they would appear
+// twice in responses.
+const DERIVED_KEYS: &[&str] = &["sidecars", "vod", "etag"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+#[serde(rename_all = "lowercase")]
+enum Visibility {
+    Public,
+    Unlisted,
+    Private,
+    Scheduled,
+}
+
+impl Entry {
+    fn sort_key(&self) -> (DateTime<Utc>, &str) {
+        (self.created_at, &self.id)
+    }
+
+    // Files predating `visibility` used `hidden: true` to mean "not listed".
+    fn visibility(&self) -> Visibility {
+        match (self.visibility, self.hidden) {
+            (Some(v), _) => v,
+            (None, Some(true)) => Visibility::Unlisted,
+            (None, _) => Visibility::Public,
+        }
+    }
+
+    // Scheduled entries are private until `publish_at` has passed.
+    fn effective_visibility(&self, now: DateTime<Utc>) -> Visibility {
+        match self.visibility() {
+            Visibility::Scheduled if self.publish_at.is_some_and(|t| t <= now) => {
+                Visibility::Public
+            }
+            Visibility::Scheduled => Visibility::Private,
+            v => v,
+        }
+    }
+
+    fn is_listed(&self, authorized: bool, now: DateTime<Utc>) -> bool {
+        match self.effective_visibility(now) {
+            Visibility::Public => true,
+            Visibility::Private => authorized,
+            Visibility::Unlisted | Visibility::Scheduled => false,
+        }
+    }
+
+    // Kinds with a parent may reference a span of a parent entry; the reference is
+    // optional, but when present it must be complete.
+    fn check_schema(&self, has_parent: bool) -> Result<(), String> {
+        if self.visibility() == Visibility::Scheduled && self.publish_at.is_none() {
+            return Err("scheduled visibility requires publish_at".into());
+        }
+        chapters::check(&self.chapters, self.duration)?;
+        songs::check(&self.setlist, self.duration)?;
+        if !has_parent {
+            return Ok(());
+        }
+        match (&self.vod_id, self.start_offset, self.end_offset) {
+            (None, None, None) => Ok(()),
+            (Some(_), Some(start), Some(end)) if start < end => Ok(()),
+            (Some(_), Some(_), Some(_)) => Err("start_offset must be before end_offset".into()),
+            (Some(_), _, _) => Err("vod_id requires start_offset and end_offset".into()),
+            (None, _, _) => Err("start_offset and end_offset require vod_id".into()),
+        }
+    }
+
+    // Unauthenticated clients only see the extra fields listed in `public_fields`.
+    fn redact(mut self, config: &ArchiveConfig, authorized: bool) -> Self {
+        if let (Some(fields), false) = (&config.public_fields, authorized) {
+            self.extra.retain(|k, _| fields.contains(k));
+        }
+        self
+    }
+
+    fn is_reachable(&self, authorized: bool, now: DateTime<Utc>) -> bool {
+        self.effective_visibility(now) != Visibility::Private || authorized
+    }
 }
 
 fn parse_duration_flex<'de, D>(deserializer: D) -> Result<Duration, D::Error>
@@ -96,13 +1624,35 @@ where
     }
 
     match Either::deserialize(deserializer)? {
-        Either::Float(secs) => Ok(Duration::from_secs_f64(secs)),
+        Either::Float(secs) => Duration::try_from_secs_f64(secs)
+            .map_err(|_| de::Error::custom("invalid duration seconds")),
         Either::String(s) => {
             parse_duration(&s).map_err(|_| de::Error::custom("invalid duration string"))
         }
     }
 }
 
+fn parse_duration_flex_opt<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(crate = "rocket::serde")]
+    struct Flex(#[serde(deserialize_with = "parse_duration_flex")] Duration);
+
+    Ok(Option::<Flex>::deserialize(deserializer)?.map(|f| f.0))
+}
+
+fn serialize_duration_opt<S>(dur: &Option<Duration>, ser: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match dur {
+        Some(dur) => serialize_duration(dur, ser),
+        None => ser.serialize_none(),
+    }
+}
+
 fn serialize_duration<S>(dur: &Duration, ser: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -121,13 +1671,1423 @@ where
     ser.serialize_str(&s)
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(crate = "rocket::serde")]
-struct ErrorStatus {
-    error: u16,
+#[catch(default)]
+fn default_catcher(status: Status, _: &Request) -> Problem {
+    status.into()
 }
 
-#[catch(default)]
-fn default_catcher(status: Status, _: &Request) -> Json<ErrorStatus> {
-    Json(ErrorStatus { error: status.code })
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::figment::Figment;
+    use rocket::http::Header;
+    use rocket::local::blocking::Client;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    const V1: &str =
+        r#"{"id":"v1","title":"One","created_at":"2025-03-01T12:00:00Z","duration":"1h"}"#;
+
+    // An archive with the given files under a fresh root, and `secret` as the only token.
+    fn client(files: &[(&str, &str)]) -> (TempDir, Client) {
+        client_with(files, &[])
+    }
+
+    fn client_with(files: &[(&str, &str)], config: &[(&str, Value)]) -> (TempDir, Client) {
+        let root = tempfile::tempdir().unwrap();
+        for (path, content) in files {
+            let path = root.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        let mut figment = Figment::from(rocket::Config::debug_default())
+            .merge(("log_level", "off"))
+            .merge(("archive.root", root.path()))
+            .merge(("archive.tokens", ["secret"]));
+        for (key, value) in config {
+            figment = figment.merge((format!("archive.{key}"), value));
+        }
+        let client = Client::tracked(rocket().configure(figment)).unwrap();
+        (root, client)
+    }
+
+    fn get_json(client: &Client, uri: &str) -> Value {
+        let response = client.get(uri.to_owned()).dispatch();
+        assert_eq!(response.status(), Status::Ok, "{uri}");
+        response.into_json().unwrap()
+    }
+
+    // Watcher-driven changes arrive asynchronously; polling covers two poll intervals.
+    fn eventually(mut check: impl FnMut() -> bool) {
+        for _ in 0..100 {
+            if check() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        panic!("condition not reached within 10s");
+    }
+
+    fn bearer() -> Header<'static> {
+        Header::new("Authorization", "Bearer secret")
+    }
+
+    fn vod(id: &str, created_at: &str, duration: &str) -> String {
+        json!({ "id": id, "title": id, "created_at": created_at, "duration": duration }).to_string()
+    }
+
+    fn ids(page: &Value) -> Vec<&str> {
+        page["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["id"].as_str().unwrap())
+            .collect()
+    }
+
+    // A yt-dlp live chat log with one message per `(offset_ms, author, text)`.
+    fn chat_log(messages: &[(u64, &str, &str)]) -> String {
+        let mut log = String::new();
+        for (i, (offset, author, text)) in messages.iter().enumerate() {
+            let renderer = json!({
+                "id": format!("m{i}"),
+                "authorName": { "simpleText": author },
+                "authorExternalChannelId": format!("UC{author}"),
+                "message": { "runs": [{ "text": text }] },
+            });
+            let line = json!({ "replayChatItemAction": {
+                "actions": [{ "addChatItemAction": { "item": { "liveChatTextMessageRenderer": renderer } } }],
+                "videoOffsetTimeMsec": offset.to_string(),
+            } });
+            log.push_str(&line.to_string());
+            log.push('\n');
+        }
+        log
+    }
+
+    // The reported failure for the file whose name ends with `file`, if any.
+    fn failure(client: &Client, file: &str) -> Option<Value> {
+        let failures = client
+            .get("/api/admin/failures")
+            .header(bearer())
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+        failures
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|f| f["path"].as_str().unwrap().ends_with(file))
+            .cloned()
+    }
+
+    #[test]
+    fn route_parameters() {
+        let (_root, client) = client(&[("vods/v1.json", V1)]);
+        let status = |uri: &str| client.get(uri).dispatch().status();
+        assert_eq!(status("/api/vods/v1"), Status::Ok);
+        assert_eq!(status("/api/vods%2F..%2Fvods/v1"), Status::BadRequest);
+        assert_eq!(status("/api/vods/..%2Fv1"), Status::BadRequest);
+        assert_eq!(status("/api/vods/v1%2F"), Status::BadRequest);
+        assert_eq!(status("/api/vods/%2E%2E"), Status::BadRequest);
+        assert_eq!(status("/api/../vods"), Status::BadRequest);
+        assert_eq!(status("/api/./vods"), Status::BadRequest);
+        assert_eq!(status("/api/vods/./v1"), Status::BadRequest);
+        assert_eq!(status("/api/nope"), Status::NotFound);
+        assert_eq!(status("/api/nope/v1"), Status::NotFound);
+        assert_eq!(status("/api/vods/v2"), Status::NotFound);
+    }
+
+    #[test]
+    fn cursor_round_trip() {
+        let cursor = Cursor {
+            created_at: "2025-03-01T12:00:00.5Z".parse().unwrap(),
+            id: "a|b".to_owned(),
+        };
+        let decoded = Cursor::decode(&cursor.encode()).unwrap();
+        assert_eq!(decoded.created_at, cursor.created_at);
+        assert_eq!(decoded.id, "a|b");
+        assert!(Cursor::decode("not base64!").is_none());
+        assert!(Cursor::decode(&URL_SAFE_NO_PAD.encode("no separator")).is_none());
+        assert!(Cursor::decode(&URL_SAFE_NO_PAD.encode("yesterday|v1")).is_none());
+    }
+
+    #[test]
+    fn pagination() {
+        let a = vod("a", "2025-03-01T00:00:00Z", "1h");
+        let b = vod("b", "2025-03-02T00:00:00Z", "1h");
+        let c = vod("c", "2025-03-02T00:00:00Z", "1h");
+        let (root, client) = client(&[
+            ("vods/a.json", &a),
+            ("vods/b.json", &b),
+            ("vods/c.json", &c),
+        ]);
+        let first = get_json(&client, "/api/vods?limit=2");
+        assert_eq!(ids(&first), ["c", "b"]);
+        // Entries added ahead of the cursor must not shift the next page.
+        let d = vod("d", "2025-03-03T00:00:00Z", "1h");
+        std::fs::write(root.path().join("vods/d.json"), d).unwrap();
+        let second = get_json(&client, first["next"].as_str().unwrap());
+        assert_eq!(ids(&second), ["a"]);
+        assert!(second["next"].is_null());
+        let status = client.get("/api/vods?cursor=bogus").dispatch().status();
+        assert_eq!(status, Status::BadRequest);
+    }
+
+    #[test]
+    fn watches_for_changes() {
+        let (root, client) = client(&[("vods/v1.json", V1)]);
+        let v2 = vod("v2", "2025-03-02T00:00:00Z", "1h");
+        std::fs::write(root.path().join("vods/v2.json"), &v2).unwrap();
+        eventually(|| client.get("/api/vods/v2").dispatch().status() == Status::Ok);
+        std::fs::remove_file(root.path().join("vods/v1.json")).unwrap();
+        eventually(|| client.get("/api/vods/v1").dispatch().status() == Status::NotFound);
+    }
+
+    #[test]
+    fn polls_when_configured() {
+        let (root, client) = client_with(&[("vods/v1.json", V1)], &[("poll", json!(true))]);
+        assert_eq!(get_json(&client, "/api/status")["watcher"], "poll");
+        let v2 = vod("v2", "2025-03-02T00:00:00Z", "1h");
+        std::fs::write(root.path().join("vods/v2.json"), &v2).unwrap();
+        eventually(|| client.get("/api/vods/v2").dispatch().status() == Status::Ok);
+    }
+
+    #[test]
+    fn indexes_kind_directories_created_later() {
+        let (root, client) = client(&[("vods/v1.json", V1)]);
+        let status = client.get("/api/clips").dispatch().status();
+        assert_eq!(status, Status::InternalServerError);
+        let c1 = vod("c1", "2025-03-02T00:00:00Z", "1m");
+        std::fs::create_dir(root.path().join("clips")).unwrap();
+        std::fs::write(root.path().join("clips/c1.json"), &c1).unwrap();
+        eventually(|| client.get("/api/clips/c1").dispatch().status() == Status::Ok);
+        let c2 = vod("c2", "2025-03-03T00:00:00Z", "1m");
+        std::fs::write(root.path().join("clips/c2.json"), &c2).unwrap();
+        eventually(|| client.get("/api/clips/c2").dispatch().status() == Status::Ok);
+    }
+
+    #[test]
+    fn search_ranks_titles_and_survives_wide_spaces() {
+        let description = format!("{}\u{3000}{} needle", "x".repeat(10), "y".repeat(60));
+        let a = json!({
+            "id": "a", "title": "Plain", "description": description,
+            "created_at": "2025-03-01T00:00:00Z", "duration": "1h",
+        });
+        let b = json!({
+            "id": "b", "title": "Needle night",
+            "created_at": "2025-03-02T00:00:00Z", "duration": "1h",
+        });
+        let (_root, client) = client(&[
+            ("vods/a.json", &a.to_string()),
+            ("vods/b.json", &b.to_string()),
+        ]);
+        let hits = get_json(&client, "/api/search?q=needle");
+        let hits = hits.as_array().unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0]["entry"]["id"], "b");
+        assert_eq!(hits[0]["title"], "<mark>Needle</mark> night");
+        assert!(hits[1]["snippet"].as_str().unwrap().starts_with("…yyy"));
+        assert_eq!(get_json(&client, "/api/search?q=nothing"), json!([]));
+    }
+
+    #[test]
+    fn list_filters() {
+        let a = vod("a", "2025-03-01T00:00:00Z", "30m");
+        let b = vod("b", "2025-03-02T00:00:00Z", "2h");
+        let c = vod("c", "2025-03-03T12:00:00Z", "3h");
+        let (_root, client) = client(&[
+            ("vods/a.json", &a),
+            ("vods/b.json", &b),
+            ("vods/c.json", &c),
+        ]);
+        let list = |query: &str| {
+            let page = get_json(&client, &format!("/api/vods?{query}"));
+            ids(&page)
+                .into_iter()
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(list("after=2025-03-02"), ["c", "b"]);
+        assert_eq!(list("before=2025-03-02T00:00:00Z"), ["a"]);
+        assert_eq!(list("after=2025-03-02&before=2025-03-03"), ["b"]);
+        assert_eq!(list("min_duration=1h"), ["c", "b"]);
+        assert_eq!(list("max_duration=7200"), ["b", "a"]);
+        assert_eq!(list("min_duration=1h&max_duration=2h30m&limit=1"), ["b"]);
+        for query in ["after=yesterday", "min_duration=soon", "max_duration=-1"] {
+            let response = client.get(format!("/api/vods?{query}")).dispatch();
+            assert_eq!(response.status(), Status::BadRequest, "{query}");
+        }
+    }
+
+    #[test]
+    fn visibility_rules() {
+        let entry = |id: &str, extra: Value| {
+            let mut entry = json!({
+                "id": id, "title": id, "created_at": "2025-03-01T00:00:00Z", "duration": "1h",
+            });
+            entry
+                .as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            (format!("vods/{id}.json"), entry.to_string())
+        };
+        let files = [
+            entry("public", json!({})),
+            entry("unlisted", json!({ "visibility": "unlisted" })),
+            entry("legacy", json!({ "hidden": true })),
+            entry("private", json!({ "visibility": "private" })),
+            entry(
+                "released",
+                json!({ "visibility": "scheduled", "publish_at": "2020-01-01T00:00:00Z" }),
+            ),
+            entry(
+                "upcoming",
+                json!({ "visibility": "scheduled", "publish_at": "2999-01-01T00:00:00Z" }),
+            ),
+            entry("undated", json!({ "visibility": "scheduled" })),
+        ];
+        let files: Vec<_> = files
+            .iter()
+            .map(|(p, c)| (p.as_str(), c.as_str()))
+            .collect();
+        let (_root, client) = client(&files);
+        let listed = |token: Option<&str>| {
+            let mut request = client.get("/api/vods");
+            if let Some(token) = token {
+                request = request.header(Header::new("Authorization", format!("Bearer {token}")));
+            }
+            let page: Value = request.dispatch().into_json().unwrap();
+            let mut ids: Vec<_> = ids(&page).into_iter().map(str::to_owned).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(listed(None), ["public", "released"]);
+        assert_eq!(listed(Some("wrong")), ["public", "released"]);
+        assert_eq!(
+            listed(Some("secret")),
+            ["private", "public", "released", "upcoming"]
+        );
+        let status = |id: &str, auth: bool| {
+            let mut request = client.get(format!("/api/vods/{id}"));
+            if auth {
+                request = request.header(bearer());
+            }
+            request.dispatch().status()
+        };
+        for id in ["public", "unlisted", "legacy", "released"] {
+            assert_eq!(status(id, false), Status::Ok, "{id}");
+        }
+        for id in ["private", "upcoming"] {
+            assert_eq!(status(id, false), Status::NotFound, "{id}");
+            assert_eq!(status(id, true), Status::Ok, "{id}");
+        }
+        assert_eq!(status("undated", true), Status::InternalServerError);
+        let undated = failure(&client, "undated.json").unwrap();
+        assert_eq!(undated["error"], "scheduled visibility requires publish_at");
+    }
+
+    #[test]
+    fn merge_patch_rules() {
+        let mut target = json!({ "a": 1, "b": { "c": 2, "d": 3 }, "e": [1] });
+        merge_patch(
+            &mut target,
+            &json!({ "a": null, "b": { "c": 4 }, "e": [2], "f": { "g": null } }),
+        );
+        assert_eq!(
+            target,
+            json!({ "b": { "c": 4, "d": 3 }, "e": [2], "f": {} })
+        );
+        merge_patch(&mut target, &json!("x"));
+        assert_eq!(target, json!("x"));
+    }
+
+    #[test]
+    fn write_api() {
+        let (root, client) = client(&[("vods/v1.json", V1)]);
+        let path = root.path().join("vods/v2.json");
+        let v2 = vod("v2", "2025-03-02T00:00:00Z", "1h");
+        let post = |body: &str| {
+            client
+                .post("/api/vods")
+                .header(ContentType::JSON)
+                .header(bearer())
+                .body(body)
+                .dispatch()
+        };
+        let response = client
+            .post("/api/vods")
+            .header(ContentType::JSON)
+            .body(&v2)
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(post(r#"{"id":"v2"}"#).status(), Status::UnprocessableEntity);
+        assert_eq!(
+            post(&vod("../x", "2025-03-02T00:00:00Z", "1h")).status(),
+            Status::BadRequest
+        );
+        let response = post(&v2);
+        assert_eq!(response.status(), Status::Created);
+        assert_eq!(response.headers().get_one("Location"), Some("/api/vods/v2"));
+        let mut etag = response
+            .headers()
+            .get_one("Entry-Version")
+            .unwrap()
+            .to_owned();
+        assert_eq!(post(&v2).status(), Status::Conflict);
+        assert_eq!(get_json(&client, "/api/vods/v2")["title"], "v2");
+
+        let mut replacement: Value = serde_json::from_str(&v2).unwrap();
+        replacement["title"] = json!("Two");
+        replacement["custom"] = json!({ "kept": true });
+        let response = client
+            .put("/api/vods/v2")
+            .header(ContentType::JSON)
+            .header(bearer())
+            .header(Header::new("If-Match", etag.clone()))
+            .body(replacement.to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        etag = response
+            .headers()
+            .get_one("Entry-Version")
+            .unwrap()
+            .to_owned();
+        let stored: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(stored, replacement);
+
+        let response = client
+            .patch("/api/vods/v2")
+            .header(ContentType::JSON)
+            .header(bearer())
+            .header(Header::new("If-Match", etag.clone()))
+            .body(r#"{"title":"Patched","custom":{"kept":null,"added":1}}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        etag = response
+            .headers()
+            .get_one("Entry-Version")
+            .unwrap()
+            .to_owned();
+        let stored: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(stored["title"], "Patched");
+        assert_eq!(stored["custom"], json!({ "added": 1 }));
+        assert_eq!(get_json(&client, "/api/vods/v2")["title"], "Patched");
+
+        let response = client
+            .patch("/api/vods/v2")
+            .header(ContentType::JSON)
+            .header(bearer())
+            .header(Header::new("If-Match", etag.clone()))
+            .body(r#"{"id":"v3"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let response = client
+            .delete("/api/vods/v2")
+            .header(bearer())
+            .header(Header::new("If-Match", etag))
+            .dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+        assert!(!path.exists());
+        assert_eq!(
+            client.get("/api/vods/v2").dispatch().status(),
+            Status::NotFound
+        );
+        // Writes go through a temporary file that is renamed into place.
+        let leftovers: Vec<_> = std::fs::read_dir(root.path().join("vods"))
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(leftovers, ["v1.json"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_keep_file_modes() {
+        use std::os::unix::fs::PermissionsExt;
+        let (root, client) = client(&[("vods/v1.json", V1)]);
+        let path = root.path().join("vods/v1.json");
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+        let etag = etag(V1.as_bytes());
+        let response = client
+            .patch("/api/vods/v1")
+            .header(ContentType::JSON)
+            .header(bearer())
+            .header(Header::new("If-Match", etag))
+            .body(r#"{"title":"Patched"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), 0o640);
+        let response = client
+            .post("/api/vods")
+            .header(ContentType::JSON)
+            .header(bearer())
+            .body(vod("v2", "2025-03-02T00:00:00Z", "1h"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+        // New files follow the umask rather than the temporary file's 0600.
+        assert_ne!(mode(&root.path().join("vods/v2.json")), 0o600);
+    }
+
+    #[test]
+    fn preconditions() {
+        let (_root, client) = client(&[("vods/v1.json", V1)]);
+        let patch = |if_match: Option<&str>| {
+            let mut request = client
+                .patch("/api/vods/v1")
+                .header(ContentType::JSON)
+                .header(bearer())
+                .body(r#"{"title":"Patched"}"#);
+            if let Some(tag) = if_match {
+                request = request.header(Header::new("If-Match", tag.to_owned()));
+            }
+            request.dispatch().status()
+        };
+        assert_eq!(patch(None), Status::PreconditionRequired);
+        assert_eq!(patch(Some(r#""stale""#)), Status::PreconditionFailed);
+        let current = etag(V1.as_bytes());
+        assert_eq!(patch(Some(&format!(r#""stale", {current}"#))), Status::Ok);
+        assert_eq!(patch(Some(&current)), Status::PreconditionFailed);
+        assert_eq!(patch(Some("*")), Status::Ok);
+        let response = client
+            .delete("/api/vods/v1")
+            .header(bearer())
+            .header(Header::new("If-Match", current))
+            .dispatch();
+        assert_eq!(response.status(), Status::PreconditionFailed);
+
+        for uri in ["/api/vods/v1", "/api/vods"] {
+            let response = client.get(uri).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            let tag = response.headers().get_one("ETag").unwrap().to_owned();
+            for header in [
+                tag.clone(),
+                format!("W/{tag}"),
+                format!(r#""other", {tag}"#),
+            ] {
+                let response = client
+                    .get(uri)
+                    .header(Header::new("If-None-Match", header))
+                    .dispatch();
+                assert_eq!(response.status(), Status::NotModified);
+                assert_eq!(response.headers().get_one("ETag"), Some(tag.as_str()));
+                assert!(response.into_string().is_none());
+            }
+            let response = client
+                .get(uri)
+                .header(Header::new("If-None-Match", r#""other""#))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
+    }
+
+    #[test]
+    fn failures_are_reported() {
+        let bad_duration = vod("v2", "2025-03-02T00:00:00Z", "forever");
+        let (_root, client) = client(&[
+            ("vods/v1.json", V1),
+            ("vods/v2.json", &bad_duration),
+            ("vods/v3.json", "{\n  \"id\": \"v3\",\n  oops\n}"),
+        ]);
+        assert_eq!(ids(&get_json(&client, "/api/vods")), ["v1"]);
+        let response = client.get("/api/admin/failures").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let v2 = failure(&client, "v2.json").unwrap();
+        assert_eq!(v2["kind"], "vods");
+        assert!(v2["error"].as_str().unwrap().contains("duration"), "{v2}");
+        assert_eq!(v2["line"], 1);
+        let v3 = failure(&client, "v3.json").unwrap();
+        assert_eq!(
+            (v3["line"].as_u64(), v3["column"].as_u64()),
+            (Some(3), Some(3))
+        );
+        let response = client.get("/api/vods/v3").dispatch();
+        assert_eq!(response.status(), Status::InternalServerError);
+    }
+
+    #[test]
+    fn extra_fields() {
+        let v1 = r#"{"id":"v1","title":"One","created_at":"2025-03-01T12:00:00Z","duration":"1h","game":"Chess","source":{"url":"https://example.com/1"},"uploader":"me"}"#;
+        let files = [("vods/v1.json", v1)];
+        let (_root, client) = client(&files);
+        let entry = get_json(&client, "/api/vods/v1");
+        assert_eq!(entry["game"], "Chess");
+        assert_eq!(entry["source"]["url"], "https://example.com/1");
+        assert_eq!(entry["uploader"], "me");
+        assert_eq!(
+            get_json(&client, "/api/vods")["entries"][0]["game"],
+            "Chess"
+        );
+
+        let (_root, client) = client_with(&files, &[("public_fields", json!(["game"]))]);
+        let entry = get_json(&client, "/api/vods/v1");
+        assert_eq!(entry["game"], "Chess");
+        assert!(entry.get("source").is_none() && entry.get("uploader").is_none());
+        assert!(
+            get_json(&client, "/api/vods")["entries"][0]
+                .get("uploader")
+                .is_none()
+        );
+        let entry = client
+            .get("/api/vods/v1")
+            .header(bearer())
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+        assert_eq!(entry["uploader"], "me");
+    }
+
+    #[test]
+    fn clips_reference_vods() {
+        let clip = |id: &str, vod_id: &str, start: &str, end: &str| {
+            let mut clip: Value =
+                serde_json::from_str(&vod(id, "2025-03-02T00:00:00Z", "1m")).unwrap();
+            clip["vod_id"] = json!(vod_id);
+            clip["start_offset"] = json!(start);
+            clip["end_offset"] = json!(end);
+            clip.to_string()
+        };
+        let (c1, c2) = (clip("c1", "v1", "30m", "31m"), clip("c2", "v1", "5m", "6m"));
+        let (c3, c4) = (
+            clip("c3", "gone", "1m", "2m"),
+            clip("c4", "v1", "59m", "2h"),
+        );
+        let mut c5: Value = serde_json::from_str(&c1).unwrap();
+        c5["id"] = json!("c5");
+        c5.as_object_mut().unwrap().remove("end_offset");
+        let mut c6: Value = serde_json::from_str(&c1).unwrap();
+        c6["id"] = json!("c6");
+        c6["end_offset"] = json!("10m");
+        let (c5, c6) = (c5.to_string(), c6.to_string());
+        let (_root, client) = client(&[
+            ("vods/v1.json", V1),
+            ("clips/c1.json", &c1),
+            ("clips/c2.json", &c2),
+            ("clips/c3.json", &c3),
+            ("clips/c4.json", &c4),
+            ("clips/c5.json", &c5),
+            ("clips/c6.json", &c6),
+        ]);
+        let entry = get_json(&client, "/api/clips/c1");
+        assert_eq!(entry["vod_id"], "v1");
+        assert_eq!(entry["vod"]["title"], "One");
+        assert!(get_json(&client, "/api/clips/c3").get("vod").is_none());
+        let children = get_json(&client, "/api/vods/v1/clips");
+        let children: Vec<_> = children
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| &e["id"])
+            .collect();
+        assert_eq!(children, ["c2", "c1", "c4"]);
+        assert_eq!(get_json(&client, "/api/vods/v1/highlights"), json!([]));
+        assert_eq!(
+            client.get("/api/clips/c1/vods").dispatch().status(),
+            Status::NotFound
+        );
+        assert_eq!(
+            client.get("/api/vods/v9/clips").dispatch().status(),
+            Status::NotFound
+        );
+
+        let error = |file| failure(&client, file).map(|f| f["error"].as_str().unwrap().to_owned());
+        assert_eq!(error("c1.json"), None);
+        assert!(error("c3.json").unwrap().contains("missing vods/gone"));
+        assert!(error("c4.json").unwrap().contains("past the end"));
+        assert!(
+            error("c5.json")
+                .unwrap()
+                .contains("requires start_offset and end_offset")
+        );
+        assert!(error("c6.json").unwrap().contains("before end_offset"));
+    }
+
+    #[test]
+    fn media_ranges() {
+        let media: String = ('a'..='z').collect();
+        let (_root, client) = client(&[("vods/v1.json", V1), ("vods/v1.mp4", &media)]);
+        let get = |range: Option<&str>| {
+            let mut request = client.get("/api/vods/v1/media");
+            if let Some(range) = range {
+                request = request.header(Header::new("Range", range.to_owned()));
+            }
+            request.dispatch()
+        };
+        let response = get(None);
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.content_type(),
+            Some(ContentType::new("video", "mp4"))
+        );
+        assert_eq!(response.headers().get_one("Accept-Ranges"), Some("bytes"));
+        // Rocket writes Content-Length from the sized body.
+        assert_eq!(response.body().preset_size(), Some(26));
+        assert_eq!(response.into_string().unwrap(), media);
+
+        let response = get(Some("bytes=2-4"));
+        assert_eq!(response.status(), Status::PartialContent);
+        assert_eq!(
+            response.headers().get_one("Content-Range"),
+            Some("bytes 2-4/26")
+        );
+        assert_eq!(response.body().preset_size(), Some(3));
+        assert_eq!(response.into_string().unwrap(), "cde");
+        let response = get(Some("bytes=-2"));
+        assert_eq!(response.status(), Status::PartialContent);
+        assert_eq!(response.into_string().unwrap(), "yz");
+
+        let response = get(Some("bytes=26-"));
+        assert_eq!(response.status(), Status::RangeNotSatisfiable);
+        assert_eq!(
+            response.headers().get_one("Content-Range"),
+            Some("bytes */26")
+        );
+        let status = client.get("/api/vods/v9/media").dispatch().status();
+        assert_eq!(status, Status::NotFound);
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let mut c1: Value = serde_json::from_str(&vod("c1", "2025-03-02T00:00:00Z", "1m")).unwrap();
+        c1["vod_id"] = json!("v1");
+        c1["start_offset"] = json!("1m");
+        c1["end_offset"] = json!("2m");
+        // Stale server-derived keys in a file must not shadow the real ones.
+        c1["sidecars"] = json!(["stale"]);
+        c1["vod"] = json!({ "id": "stale" });
+        let c1 = c1.to_string();
+        let (root, client) = client(&[
+            ("vods/v1.json", V1),
+            ("clips/c1.json", &c1),
+            ("clips/c1.info.json", "{}"),
+        ]);
+        let response = client.get("/api/clips/c1").dispatch();
+        let version = response
+            .headers()
+            .get_one("Entry-Version")
+            .unwrap()
+            .to_owned();
+        assert_eq!(version, etag(c1.as_bytes()));
+        let body = response.into_string().unwrap();
+        assert_eq!(body.matches(r#""sidecars""#).count(), 1, "{body}");
+        assert_eq!(body.matches(r#""vod""#).count(), 1, "{body}");
+        let fetched: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(fetched["sidecars"][0]["suffix"], "info.json");
+        assert_eq!(fetched["vod"]["id"], "v1");
+
+        let response = client
+            .put("/api/clips/c1")
+            .header(ContentType::JSON)
+            .header(bearer())
+            .header(Header::new("If-Match", version))
+            .body(&body)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let stored: Value =
+            serde_json::from_slice(&std::fs::read(root.path().join("clips/c1.json")).unwrap())
+                .unwrap();
+        for key in ["sidecars", "vod", "etag"] {
+            assert!(stored.get(key).is_none(), "{stored}");
+        }
+        assert_eq!(get_json(&client, "/api/clips/c1"), fetched);
+    }
+
+    #[test]
+    fn entry_tags_cover_embedded_data() {
+        let mut c1: Value = serde_json::from_str(&vod("c1", "2025-03-02T00:00:00Z", "1m")).unwrap();
+        c1["vod_id"] = json!("v1");
+        c1["start_offset"] = json!("1m");
+        c1["end_offset"] = json!("2m");
+        let (root, client) = client(&[("vods/v1.json", V1), ("clips/c1.json", &c1.to_string())]);
+        let tag = || {
+            let response = client.get("/api/clips/c1").dispatch();
+            response.headers().get_one("ETag").unwrap().to_owned()
+        };
+        let fresh = |tag: &str| {
+            client
+                .get("/api/clips/c1")
+                .header(Header::new("If-None-Match", tag.to_owned()))
+                .dispatch()
+                .status()
+                == Status::Ok
+        };
+        let first = tag();
+        assert!(!fresh(&first));
+        std::fs::write(root.path().join("clips/c1.info.json"), "{}").unwrap();
+        eventually(|| fresh(&first));
+        let second = tag();
+        let v1 = V1.replace(r#""One""#, r#""Renamed""#);
+        std::fs::write(root.path().join("vods/v1.json"), v1).unwrap();
+        // A write can be observed half done, so wait for the final title.
+        eventually(|| get_json(&client, "/api/clips/c1")["vod"]["title"] == "Renamed");
+        assert!(fresh(&second));
+        // The file itself did not change, so neither did its version.
+        let response = client.get("/api/clips/c1").dispatch();
+        let version = response.headers().get_one("Entry-Version").unwrap();
+        assert_eq!(version, etag(c1.to_string().as_bytes()));
+    }
+
+    #[test]
+    fn chat_windows() {
+        let messages: Vec<_> = (0..600).map(|s| (s * 1000, "viewer", "hello")).collect();
+        let log = chat_log(&messages);
+        let (_root, client) = client(&[
+            ("rplay/r1.json", &vod("r1", "2025-03-01T00:00:00Z", "10m")),
+            ("rplay/r1.live_chat.json", &log),
+            ("rplay/r2.json", &vod("r2", "2025-03-02T00:00:00Z", "10m")),
+        ]);
+        let offsets = |page: &Value| -> Vec<u64> {
+            let messages = page["messages"].as_array().unwrap();
+            messages
+                .iter()
+                .map(|m| m["offset_ms"].as_u64().unwrap())
+                .collect()
+        };
+        let page = get_json(&client, "/api/rplay/r1/chat?from=5m&to=5m3s");
+        assert_eq!(page["total"], 600);
+        assert_eq!(offsets(&page), [300_000, 301_000, 302_000]);
+        assert_eq!(page["messages"][0]["author"], "viewer");
+        assert!(page["next"].is_null());
+
+        let mut uri = "/api/rplay/r1/chat?from=1m&to=1m5s&limit=2".to_owned();
+        let mut seen = Vec::new();
+        loop {
+            let page = get_json(&client, &uri);
+            assert!(page["messages"].as_array().unwrap().len() <= 2);
+            seen.extend(offsets(&page));
+            let Some(next) = page["next"].as_str() else {
+                break;
+            };
+            uri = next.to_owned();
+        }
+        assert_eq!(seen, [60_000, 61_000, 62_000, 63_000, 64_000]);
+        let tail = get_json(&client, "/api/rplay/r1/chat?from=9m59s");
+        assert_eq!(offsets(&tail), [599_000]);
+
+        let status = |uri| client.get(uri).dispatch().status();
+        assert_eq!(status("/api/rplay/r1/chat?from=soon"), Status::BadRequest);
+        assert_eq!(status("/api/rplay/r1/chat?cursor=%21"), Status::BadRequest);
+        assert_eq!(status("/api/rplay/r2/chat"), Status::NotFound);
+    }
+
+    #[test]
+    fn chat_activity_spikes() {
+        let mut messages: Vec<(u64, &str, &str)> =
+            (0..20).map(|m| (m * 60_000, "a", "hi")).collect();
+        messages.extend((0..30).map(|i| (600_000 + i * 1000, "b", "POG :wave: :wave:")));
+        messages.extend((0..20).map(|i| (660_000 + i * 1000, "c", "pog nice")));
+        messages.extend((0..12).map(|i| (900_000 + i * 1000, "d", "lol")));
+        messages.sort_by_key(|m| m.0);
+        let (_root, client) = client(&[
+            ("vods/v1.json", V1),
+            ("vods/v1.live_chat.json", &chat_log(&messages)),
+        ]);
+        let activity = get_json(
+            &client,
+            "/api/vods/v1/chat/activity?interval=1m&threshold=1",
+        );
+        assert_eq!(activity["interval_ms"], 60_000);
+        assert_eq!(activity["messages"], 82);
+        let histogram = activity["histogram"].as_array().unwrap();
+        assert_eq!(histogram.len(), 20);
+        assert_eq!(
+            (&histogram[9], &histogram[10], &histogram[11]),
+            (&json!(1), &json!(31), &json!(21))
+        );
+        let highlights = activity["highlights"].as_array().unwrap();
+        assert_eq!(highlights.len(), 2, "{activity}");
+        let spike = &highlights[0];
+        assert_eq!(
+            (&spike["start_ms"], &spike["end_ms"], &spike["peak_ms"]),
+            (&json!(600_000), &json!(720_000), &json!(600_000))
+        );
+        assert_eq!(spike["messages"], 52);
+        assert_eq!(spike["keywords"][0], json!({ "token": "pog", "count": 50 }));
+        assert_eq!(
+            spike["keywords"][1],
+            json!({ "token": ":wave:", "count": 30 })
+        );
+        assert_eq!(highlights[1]["start_ms"], 900_000);
+        assert!(spike["score"].as_f64() > highlights[1]["score"].as_f64());
+
+        let activity = get_json(
+            &client,
+            "/api/vods/v1/chat/activity?interval=1m&threshold=1&limit=1",
+        );
+        assert_eq!(activity["highlights"].as_array().unwrap().len(), 1);
+        let status = |uri| client.get(uri).dispatch().status();
+        assert_eq!(
+            status("/api/vods/v1/chat/activity?interval=10ms"),
+            Status::BadRequest
+        );
+        assert_eq!(
+            status("/api/vods/v1/chat/activity?threshold=NaN"),
+            Status::BadRequest
+        );
+    }
+
+    #[test]
+    fn chat_search() {
+        let vod_chat = chat_log(&[
+            (1000, "Alice", "first POGGERS moment"),
+            (2000, "Bob", "pog champ"),
+            (3000, "alice", "nothing here"),
+        ]);
+        let rplay_chat = chat_log(&[(5000, "Carol", "pog again"), (6000, "Bob", "bye")]);
+        let mut private: Value =
+            serde_json::from_str(&vod("r2", "2025-03-03T00:00:00Z", "1h")).unwrap();
+        private["visibility"] = json!("private");
+        let (root, client) = client(&[
+            ("vods/v1.json", V1),
+            ("vods/v1.live_chat.json", &vod_chat),
+            ("rplay/r1.json", &vod("r1", "2025-03-02T00:00:00Z", "1h")),
+            ("rplay/r1.live_chat.json", &rplay_chat),
+            ("rplay/r2.json", &private.to_string()),
+            (
+                "rplay/r2.live_chat.json",
+                &chat_log(&[(1000, "Dave", "pog secret")]),
+            ),
+        ]);
+        let texts = |uri: &str| -> Vec<String> {
+            let hits = get_json(&client, uri);
+            let hits = hits.as_array().unwrap();
+            hits.iter()
+                .map(|h| h["message"]["text"].as_str().unwrap().to_owned())
+                .collect()
+        };
+        // The last term also matches as a prefix.
+        let mut found = texts("/api/chat/search?q=pog");
+        found.sort();
+        assert_eq!(found, ["first POGGERS moment", "pog again", "pog champ"]);
+        assert_eq!(texts("/api/chat/search?q=pog+ch"), ["pog champ"]);
+        assert_eq!(
+            texts("/api/chat/search?q=champ+again"),
+            Vec::<String>::new()
+        );
+        assert_eq!(texts("/api/chat/search?q=pog&author=CAROL"), ["pog again"]);
+        assert_eq!(texts("/api/chat/search?author=UCBob").len(), 2);
+        assert_eq!(texts("/api/chat/search?q=pog&limit=1").len(), 1);
+
+        let hits = get_json(&client, "/api/chat/search?q=again");
+        let hit = &hits[0];
+        assert_eq!((&hit["kind"], &hit["id"]), (&json!("rplay"), &json!("r1")));
+        assert_eq!(hit["message"]["offset_ms"], 5000);
+        let page = get_json(&client, hit["link"].as_str().unwrap());
+        assert_eq!(page["messages"][0]["text"], "pog again");
+        assert!(root.path().join(".chat-index/rplay/r1.json").exists());
+
+        let hits = client
+            .get("/api/chat/search?q=secret")
+            .header(bearer())
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+        assert_eq!(hits[0]["id"], "r2");
+        assert_eq!(texts("/api/chat/search?q=secret"), Vec::<String>::new());
+        let status = client.get("/api/chat/search?q=%20").dispatch().status();
+        assert_eq!(status, Status::BadRequest);
+    }
+
+    #[test]
+    fn subtitles_and_transcripts() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,500\nHello <i>world</i>\n\n\
+                   2\n00:59:59,000 --> 01:00:05,000\nclosing words\n\n\
+                   3\n01:00:10,000 --> 01:00:12,000\nwords after the end\n";
+        let (_root, client) = client(&[
+            ("vods/v1.json", V1),
+            ("vods/v1.en.srt", srt),
+            ("vods/v1.info.json", "{}"),
+        ]);
+        let response = client.get("/api/vods/v1/files/en.srt").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), srt);
+        let response = client
+            .get("/api/vods/v1/files/en.srt?format=vtt")
+            .dispatch();
+        assert_eq!(
+            response.content_type(),
+            Some(ContentType::new("text", "vtt"))
+        );
+        let vtt = response.into_string().unwrap();
+        assert!(
+            vtt.starts_with("WEBVTT\n\n00:00:01.000 --> 00:00:02.500\nHello <i>world</i>\n\n"),
+            "{vtt}"
+        );
+        // Rendering ends every cue with a blank line, the final one included.
+        let back = client
+            .get("/api/vods/v1/files/en.srt?format=srt")
+            .dispatch();
+        assert_eq!(back.into_string().unwrap(), format!("{srt}\n"));
+        let status = |uri| client.get(uri).dispatch().status();
+        assert_eq!(
+            status("/api/vods/v1/files/en.srt?format=ass"),
+            Status::BadRequest
+        );
+        assert_eq!(
+            status("/api/vods/v1/files/info.json?format=vtt"),
+            Status::BadRequest
+        );
+        assert_eq!(status("/api/vods/v1/files/de.srt"), Status::NotFound);
+
+        let hits = get_json(&client, "/api/transcripts/search?q=words");
+        let hits = hits.as_array().unwrap();
+        assert_eq!(hits.len(), 1, "{hits:?}");
+        assert_eq!(
+            (&hits[0]["start_ms"], &hits[0]["end_ms"]),
+            (&json!(3_599_000), &json!(3_600_000))
+        );
+        assert_eq!(hits[0]["suffix"], "en.srt");
+        assert_eq!(hits[0]["link"], "/api/vods/v1/media#t=3599.000");
+        let hits = get_json(&client, "/api/transcripts/search?q=world&kind=vods");
+        assert_eq!(hits[0]["snippet"], "Hello <mark>world</mark>");
+        assert_eq!(
+            get_json(&client, "/api/transcripts/search?q=world&kind=clips"),
+            json!([])
+        );
+    }
+
+    #[test]
+    fn chapters() {
+        let mut v1: Value = serde_json::from_str(V1).unwrap();
+        v1["description"] = json!("00:00 Intro\n10:00 Main = event\n");
+        let (_root, client) = client(&[("vods/v1.json", &v1.to_string())]);
+        let entry = get_json(&client, "/api/vods/v1");
+        assert_eq!(
+            entry["chapters"][1],
+            json!({ "start": "10m0s", "title": "Main = event" })
+        );
+        let get = |uri| client.get(uri).dispatch().into_string().unwrap();
+        assert_eq!(
+            get("/api/vods/v1/chapters"),
+            "WEBVTT\n\n00:00:00.000 --> 00:10:00.000\nIntro\n\n\
+             00:10:00.000 --> 01:00:00.000\nMain = event\n\n"
+        );
+        let json: Value = serde_json::from_str(&get("/api/vods/v1/chapters?format=json")).unwrap();
+        assert_eq!(
+            json["chapters"][1],
+            json!({ "startTime": 600.0, "title": "Main = event" })
+        );
+        let ffmetadata = get("/api/vods/v1/chapters?format=ffmetadata");
+        assert!(
+            ffmetadata.starts_with(";FFMETADATA1\ntitle=One\n"),
+            "{ffmetadata}"
+        );
+        assert!(ffmetadata.contains("START=600000\nEND=3600000\ntitle=Main \\= event\n"));
+        let status = client
+            .get("/api/vods/v1/chapters?format=mkv")
+            .dispatch()
+            .status();
+        assert_eq!(status, Status::BadRequest);
+
+        // Explicit chapters replace the parsed ones and are validated on write.
+        let mut v2: Value = serde_json::from_str(&vod("v2", "2025-03-02T00:00:00Z", "1h")).unwrap();
+        v2["description"] = json!("00:00 Ignored");
+        v2["chapters"] = json!([{ "start": "5m", "title": "B" }, { "start": "1m", "title": "A" }]);
+        let post = |body: &Value| {
+            client
+                .post("/api/vods")
+                .header(ContentType::JSON)
+                .header(bearer())
+                .body(body.to_string())
+                .dispatch()
+                .status()
+        };
+        assert_eq!(post(&v2), Status::UnprocessableEntity);
+        v2["chapters"] = json!([{ "start": "1m", "title": "A" }, { "start": "5m", "title": "B" }]);
+        assert_eq!(post(&v2), Status::Created);
+        let chapters = get_json(&client, "/api/vods/v2")["chapters"].clone();
+        assert_eq!(
+            chapters,
+            json!([{ "start": "1m0s", "title": "A" }, { "start": "5m0s", "title": "B" }])
+        );
+    }
+
+    #[test]
+    fn song_index() {
+        let with_setlist = |id: &str, created_at: &str, setlist: Value| {
+            let mut entry: Value = serde_json::from_str(&vod(id, created_at, "1h")).unwrap();
+            entry["setlist"] = setlist;
+            entry.to_string()
+        };
+        let v1 = with_setlist(
+            "v1",
+            "2025-03-01T00:00:00Z",
+            json!([
+                { "title": "Song A", "artist": "Xavier", "offset": "1m" },
+                { "title": "Song B", "artist": "Xavier", "offset": "10m" },
+            ]),
+        );
+        let v2 = with_setlist(
+            "v2",
+            "2025-03-05T00:00:00Z",
+            json!([{ "title": "song  a", "artist": "xavier", "offset": "5m" }]),
+        );
+        let c1 = with_setlist(
+            "c1",
+            "2025-03-03T00:00:00Z",
+            json!([{ "title": "Another Song", "artist": "Yara", "offset": 0 }]),
+        );
+        let late = with_setlist(
+            "v3",
+            "2025-03-06T00:00:00Z",
+            json!([{ "title": "Song A", "offset": "2h" }]),
+        );
+        let (_root, client) = client(&[
+            ("vods/v1.json", &v1),
+            ("vods/v2.json", &v2),
+            ("vods/v3.json", &late),
+            ("clips/c1.json", &c1),
+        ]);
+        let songs = get_json(&client, "/api/songs");
+        let songs = songs.as_array().unwrap();
+        let titles: Vec<_> = songs.iter().map(|s| s["title"].as_str().unwrap()).collect();
+        assert_eq!(titles, ["Song A", "Another Song", "Song B"]);
+        let song_a = &songs[0];
+        assert_eq!(song_a["count"], 2);
+        assert_eq!(song_a["artist"], "Xavier");
+        assert_eq!(song_a["first_performed"], "2025-03-01T00:00:00Z");
+        assert_eq!(song_a["last_performed"], "2025-03-05T00:00:00Z");
+        assert_eq!(song_a["performances"][1]["id"], "v2");
+        assert_eq!(song_a["performances"][1]["offset"], "5m0s");
+        assert_eq!(songs[1]["performances"][0]["kind"], "clips");
+
+        let titles = |uri| -> Vec<String> {
+            let songs = get_json(&client, uri);
+            let songs = songs.as_array().unwrap();
+            songs
+                .iter()
+                .map(|s| s["title"].as_str().unwrap().to_owned())
+                .collect()
+        };
+        assert_eq!(titles("/api/songs?artist=%20yara"), ["Another Song"]);
+        assert_eq!(
+            titles("/api/songs?q=so&artist=xavier"),
+            ["Song A", "Song B"]
+        );
+        assert_eq!(titles("/api/songs?q=another"), ["Another Song"]);
+        assert_eq!(titles("/api/songs?limit=1"), ["Song A"]);
+        let error = failure(&client, "v3.json").unwrap()["error"].clone();
+        assert_eq!(error, r#"song "Song A" starts after the end"#);
+    }
+
+    #[test]
+    fn feeds() {
+        let mut v2: Value =
+            serde_json::from_str(&vod("v2", "2025-03-02T00:00:00Z", "1h2m3s")).unwrap();
+        v2["title"] = json!("Tom & Jerry <live>");
+        v2["description"] = json!("A \"quoted\" stream");
+        let mut v3: Value = serde_json::from_str(&vod("v3", "2025-03-03T00:00:00Z", "1h")).unwrap();
+        v3["visibility"] = json!("unlisted");
+        let (_root, client) = client_with(
+            &[
+                ("vods/v1.json", V1),
+                ("vods/v2.json", &v2.to_string()),
+                ("vods/v3.json", &v3.to_string()),
+            ],
+            &[("base_url", json!("https://archive.example/"))],
+        );
+        let fetch = |uri| {
+            let response = client.get(uri).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            let content_type = response.content_type().unwrap().to_string();
+            (content_type, response.into_string().unwrap())
+        };
+
+        let (content_type, rss) = fetch("/api/vods/feed.rss");
+        assert_eq!(content_type, "application/rss+xml");
+        assert!(rss.contains("<atom:link href=\"https://archive.example/api/vods/feed.rss\""));
+        let items: Vec<_> = rss.split("<item>").skip(1).collect();
+        assert_eq!(items.len(), 2, "{rss}");
+        assert!(
+            items[0].contains("<title>Tom &amp; Jerry &lt;live&gt;</title>"),
+            "{rss}"
+        );
+        assert!(items[0].contains("<link>https://archive.example/api/vods/v2</link>"));
+        assert!(items[0].contains("<description>A &quot;quoted&quot; stream</description>"));
+        assert!(items[0].contains("<pubDate>Sun, 2 Mar 2025 00:00:00 +0000</pubDate>"));
+        assert!(items[0].contains("<itunes:duration>01:02:03</itunes:duration>"));
+        assert!(items[1].contains("<title>One</title>"));
+
+        let (content_type, atom) = fetch("/api/vods/feed.atom");
+        assert_eq!(content_type, "application/atom+xml");
+        assert_eq!(atom.matches("<entry>").count(), 2);
+        assert!(
+            atom.contains("<updated>2025-03-02T00:00:00Z</updated>"),
+            "{atom}"
+        );
+        assert!(atom.contains("<id>https://archive.example/api/vods/v1</id>"));
+
+        let (content_type, json) = fetch("/api/vods/feed.json");
+        assert_eq!(content_type, "application/feed+json");
+        let json: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            json["feed_url"],
+            "https://archive.example/api/vods/feed.json"
+        );
+        assert_eq!(json["items"][0]["title"], "Tom & Jerry <live>");
+        assert_eq!(json["items"][0]["_kgg"]["duration_seconds"], 3723.0);
+        assert_eq!(json["items"][1]["date_published"], "2025-03-01T12:00:00Z");
+
+        let response = client.get("/api/vods/feed.rss").dispatch();
+        let tag = response.headers().get_one("ETag").unwrap().to_owned();
+        let response = client
+            .get("/api/vods/feed.rss")
+            .header(Header::new("If-None-Match", tag))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotModified);
+        assert_eq!(
+            client.get("/api/nope/feed.rss").dispatch().status(),
+            Status::NotFound
+        );
+    }
+
+    #[test]
+    fn podcast_feed() {
+        let mut v2: Value = serde_json::from_str(&vod("v2", "2025-03-02T00:00:00Z", "1h")).unwrap();
+        v2["description"] = json!("00:00 Intro\n30:00 Talk");
+        let files = [
+            ("vods/v1.json", V1),
+            ("vods/v2.json", &v2.to_string()),
+            ("vods/v2.m4a", "0123456789"),
+            ("vods/v3.json", &vod("v3", "2025-03-03T00:00:00Z", "1h")),
+            ("vods/v3.mp4", "01234"),
+        ];
+        let podcast = json!({ "image": "/cover.png", "subcategory": "Games" });
+        let (_root, client) = client_with(
+            &files,
+            &[
+                ("base_url", json!("https://archive.example")),
+                ("podcast", podcast),
+            ],
+        );
+        let rss = client
+            .get("/api/vods/podcast.rss")
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(rss.contains("<itunes:image href=\"https://archive.example/cover.png\"/>"));
+        assert!(rss.contains(
+            "<itunes:category text=\"Leisure\">\n<itunes:category text=\"Games\"/>\n</itunes:category>"
+        ));
+        // Only entries with media become episodes.
+        let items: Vec<_> = rss.split("<item>").skip(1).collect();
+        assert_eq!(items.len(), 2, "{rss}");
+        assert!(items[0].contains(
+            "<enclosure url=\"https://archive.example/api/vods/v3/media\" length=\"5\" type=\"video/mp4\"/>"
+        ));
+        assert!(!items[0].contains("<podcast:chapters"));
+        assert!(items[1].contains(
+            "<enclosure url=\"https://archive.example/api/vods/v2/media\" length=\"10\" type=\"audio/mp4\"/>"
+        ));
+        assert!(items[1].contains("<itunes:duration>01:00:00</itunes:duration>"));
+        assert!(items[1].contains(
+            "<podcast:chapters url=\"https://archive.example/api/vods/v2/chapters?format=json\" type=\"application/json+chapters\"/>"
+        ));
+
+        let podcast = json!({ "image": "https://cdn.example/cover.png" });
+        let (_root, client) = client_with(&files, &[("podcast", podcast)]);
+        let rss = client
+            .get("/api/vods/podcast.rss")
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(rss.contains("<itunes:image href=\"https://cdn.example/cover.png\"/>"));
+        assert!(rss.contains("<itunes:category text=\"Leisure\"/>"));
+    }
+
+    #[test]
+    fn openapi_document() {
+        let dir = tempfile::tempdir().unwrap();
+        let redoc = dir.path().join("redoc.js");
+        std::fs::write(&redoc, "/* redoc */").unwrap();
+        let config = [("docs", json!(true)), ("redoc", json!(redoc))];
+        let (root, client) = client_with(&[("vods/v1.json", V1)], &config);
+        let spec = get_json(&client, "/api/openapi.json");
+        let operation = |path: &str, method: &str| spec["paths"][path][method].clone();
+        let param = |operation: &Value, name: &str| {
+            let params = operation["parameters"].as_array().unwrap();
+            params.iter().find(|p| p["name"] == name).unwrap().clone()
+        };
+        let search = operation("/api/search", "get");
+        assert_eq!(param(&search, "q")["required"], true);
+        assert_eq!(param(&search, "limit")["schema"]["type"], "integer");
+        assert_eq!(param(&search, "kind")["required"], false);
+        let chat_search = operation("/api/chat/search", "get");
+        assert_eq!(param(&chat_search, "q")["required"], false);
+        let chapters = operation("/api/{kind}/{id}/chapters", "get");
+        assert_eq!(param(&chapters, "format")["schema"]["enum"][2], "json");
+        let activity = operation("/api/{kind}/{id}/chat/activity", "get");
+        assert_eq!(param(&activity, "threshold")["schema"]["type"], "number");
+
+        let schema = |operation: &Value| -> Value {
+            operation["responses"]["200"]["content"]["application/json"]["schema"].clone()
+        };
+        assert_eq!(
+            schema(&operation("/api/status", "get"))["$ref"],
+            "#/components/schemas/IndexStats"
+        );
+        assert_eq!(
+            schema(&operation("/api/{kind}/{id}/chat", "get"))["$ref"],
+            "#/components/schemas/ChatPage"
+        );
+        assert_eq!(schema(&search)["items"]["$ref"], "#/components/schemas/Hit");
+        let schemas = &spec["components"]["schemas"];
+        for name in [
+            "ChatHit",
+            "TranscriptHit",
+            "SongSummary",
+            "Failure",
+            "Activity",
+            "Message",
+        ] {
+            assert!(schemas.get(name).is_some(), "{name} missing");
+        }
+        assert!(schemas["Failure"]["properties"].get("line").is_some());
+        let create = operation("/api/{kind}", "post");
+        assert_eq!(create["security"], json!([{ "bearer": [] }]));
+
+        let docs = client.get("/api/docs").dispatch().into_string().unwrap();
+        assert!(docs.contains(r#"<script src="docs/redoc.standalone.js">"#));
+        assert!(!docs.contains("https://"));
+        let response = client.get("/api/docs/redoc.standalone.js").dispatch();
+        assert_eq!(response.content_type(), Some(ContentType::JavaScript));
+        assert_eq!(response.into_string().unwrap(), "/* redoc */");
+
+        let figment = Figment::from(rocket::Config::debug_default())
+            .merge(("log_level", "off"))
+            .merge(("archive.root", root.path()))
+            .merge(("archive.docs", true));
+        // Docs without a local Redoc bundle are refused at startup.
+        let error = Client::tracked(rocket().configure(figment)).err().unwrap();
+        assert!(matches!(
+            error.kind(),
+            rocket::error::ErrorKind::FailedFairings(_)
+        ));
+    }
+
+    #[test]
+    fn problem_bodies() {
+        let (_root, client) = client(&[("vods/v1.json", V1)]);
+        let problem = |uri: &str, status: Status| {
+            let response = client.get(uri.to_owned()).dispatch();
+            assert_eq!(response.status(), status, "{uri}");
+            assert_eq!(
+                response.content_type(),
+                Some(ContentType::new("application", "problem+json"))
+            );
+            let id = response
+                .headers()
+                .get_one("X-Request-Id")
+                .unwrap()
+                .to_owned();
+            let body: Value = response.into_json().unwrap();
+            assert_eq!(body["status"], status.code);
+            assert_eq!(body["request_id"], id);
+            body
+        };
+        let kind = |uri| problem(uri, Status::BadRequest)["type"].clone();
+        assert_eq!(
+            kind("/api/vods?cursor=bogus"),
+            "urn:kgg:problem:invalid-cursor"
+        );
+        assert_eq!(
+            kind("/api/vods/v1/chat?cursor=bogus"),
+            "urn:kgg:problem:invalid-cursor"
+        );
+        assert_eq!(kind("/api/search"), "urn:kgg:problem:missing-parameter");
+        assert_eq!(
+            kind("/api/search?q=%2B%2B"),
+            "urn:kgg:problem:invalid-query"
+        );
+        assert_eq!(
+            kind("/api/transcripts/search"),
+            "urn:kgg:problem:missing-parameter"
+        );
+        assert_eq!(kind("/api/songs?q=-"), "urn:kgg:problem:invalid-query");
+        assert_eq!(
+            kind("/api/chat/search"),
+            "urn:kgg:problem:missing-parameter"
+        );
+        assert_eq!(
+            kind("/api/vods?after=yesterday"),
+            "urn:kgg:problem:invalid-parameter"
+        );
+        assert_eq!(
+            kind("/api/vods/v1/chapters?format=mkv"),
+            "urn:kgg:problem:invalid-parameter"
+        );
+        let body = problem("/api/search?q=%2B%2B", Status::BadRequest);
+        assert_eq!(body["detail"], r#""++" contains no words to search for"#);
+
+        let body = problem("/api/vods/v9", Status::NotFound);
+        assert_eq!(body["type"], "urn:kgg:problem:not-found");
+        assert_eq!(body["detail"], "there is no entry vods/v9");
+        let body = problem("/api/nope", Status::NotFound);
+        assert_eq!(body["type"], "urn:kgg:problem:unknown-kind");
+        // Statuses without a specific problem come from the catcher.
+        let body = problem("/api/vods/v1/media", Status::NotFound);
+        assert_eq!(
+            (&body["type"], &body["title"]),
+            (&json!("about:blank"), &json!("Not Found"))
+        );
+        assert!(body.get("detail").is_none());
+
+        let response = client
+            .post("/api/vods")
+            .header(ContentType::JSON)
+            .header(bearer())
+            .body(r#"{"id":"v2","title":"Two"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let body: Value = response.into_json().unwrap();
+        assert_eq!(body["type"], "urn:kgg:problem:invalid-entry");
+        assert!(
+            body["detail"].as_str().unwrap().contains("created_at"),
+            "{body}"
+        );
+    }
+
+    #[test]
+    fn metrics() {
+        let v2 = json!({ "id": "v2", "title": "Two", "created_at": "2025-03-02T00:00:00Z",
+            "duration": "30m", "visibility": "private" })
+        .to_string();
+        let (_root, client) = client(&[
+            ("vods/v1.json", V1),
+            ("vods/v2.json", &v2),
+            ("vods/v3.json", "{"),
+        ]);
+        get_json(&client, "/api/vods/v1");
+        get_json(&client, "/api/vods/v1");
+        assert_eq!(
+            client.get("/api/nope/v1").dispatch().status(),
+            Status::NotFound
+        );
+        let response = client.get("/metrics").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.content_type(),
+            Some(ContentType::new("text", "plain").with_params(("version", "0.0.4")))
+        );
+        let body = response.into_string().unwrap();
+        let lines: Vec<_> = body.lines().collect();
+        for line in [
+            "# TYPE kgg_entries gauge",
+            "kgg_entries{kind=\"vods\"} 2",
+            "kgg_hidden_entries{kind=\"vods\"} 1",
+            "kgg_malformed_files{kind=\"vods\"} 1",
+            "kgg_archived_duration_seconds{kind=\"vods\"} 5400",
+            "# TYPE kgg_http_requests_total counter",
+            "kgg_http_requests_total{route=\"entry\",kind=\"vods\",method=\"GET\",status=\"200\"} 2",
+            "kgg_http_request_duration_seconds_count{route=\"entry\",kind=\"vods\",method=\"GET\",status=\"200\"} 2",
+        ] {
+            assert!(lines.contains(&line), "missing {line:?} in\n{body}");
+        }
+        assert!(body.contains("kgg_scan_duration_seconds{kind=\"vods\"} "));
+        // Unknown kinds are not label values.
+        assert!(!body.contains("nope"), "{body}");
+        assert!(body.contains("route=\"entry\",kind=\"\",method=\"GET\",status=\"404\"} 1"));
+    }
 }
@@ -0,0 +1,268 @@
+use crate::problem::Problem;
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, Responder, Response};
+use rocket::serde::Serialize;
+use rocket::tokio::fs::File;
+use rocket::tokio::io::{self, AsyncRead, AsyncSeek, AsyncSeekExt, ReadBuf, SeekFrom};
+use schemars::JsonSchema;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+const MEDIA_TYPES: &[(&str, &str)] = &[
+    ("mp4", "video/mp4"),
+    ("m4v", "video/mp4"),
+    ("webm", "video/webm"),
+    ("mkv", "video/x-matroska"),
+    ("mov", "video/quicktime"),
+    ("ts", "video/mp2t"),
+    ("m4a", "audio/mp4"),
+    ("mp3", "audio/mpeg"),
+    ("opus", "audio/ogg"),
+    ("ogg", "audio/ogg"),
+    ("flac", "audio/flac"),
+    ("wav", "audio/wav"),
+    ("aac", "audio/aac"),
+];
+
+const SIDECAR_TYPES: &[(&str, &str)] = &[
+    ("json", "application/json"),
+    ("vtt", "text/vtt"),
+    ("srt", "application/x-subrip"),
+    ("ass", "text/x-ssa"),
+    ("lrc", "text/plain"),
+    ("txt", "text/plain"),
+    ("description", "text/plain"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("webp", "image/webp"),
+];
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Sidecar {
+    pub suffix: String,
+    pub size: u64,
+    pub content_type: &'static str,
+}
+
+impl Sidecar {
+    pub fn stat(path: &Path, suffix: &str) -> Option<Sidecar> {
+        let meta = std::fs::metadata(path).ok()?;
+        meta.is_file().then(|| Sidecar {
+            suffix: suffix.to_owned(),
+            size: meta.len(),
+            content_type: content_type(suffix),
+        })
+    }
+}
+
+pub fn content_type(suffix: &str) -> &'static str {
+    let ext = suffix.rsplit('.').next().unwrap_or(suffix);
+    MEDIA_TYPES
+        .iter()
+        .chain(SIDECAR_TYPES)
+        .find(|(e, _)| e.eq_ignore_ascii_case(ext))
+        .map_or("application/octet-stream", |&(_, mime)| mime)
+}
+
+pub fn find_media(entry_path: &Path) -> Option<(PathBuf, &'static str)> {
+    MEDIA_TYPES.iter().find_map(|&(ext, mime)| {
+        let path = entry_path.with_extension(ext);
+        path.is_file().then_some((path, mime))
+    })
+}
+
+pub struct RangeHeader<'r>(Option<&'r str>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RangeHeader<'r> {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(RangeHeader(req.headers().get_one("Range")))
+    }
+}
+
+impl RangeHeader<'_> {
+    // Only single `bytes` ranges are honoured; anything else is served in full,
+    // which RFC 9110 allows.
+    fn resolve(&self, len: u64) -> Span {
+        let Some(spec) = self.0.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+            return Span::Full;
+        };
+        if spec.contains(',') {
+            return Span::Full;
+        }
+        let Some((start, end)) = spec.split_once('-') else {
+            return Span::Full;
+        };
+        let (start, end) = match (start.trim().parse::<u64>(), end.trim().parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(len.saturating_sub(1))),
+            (Ok(start), Err(_)) if end.trim().is_empty() => (start, len.saturating_sub(1)),
+            (Err(_), Ok(suffix)) if start.trim().is_empty() && suffix > 0 => {
+                (len.saturating_sub(suffix), len.saturating_sub(1))
+            }
+            _ => return Span::Full,
+        };
+        if start >= len {
+            return Span::Unsatisfiable;
+        }
+        Span::Part(start, end)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Span {
+    Full,
+    Part(u64, u64),
+    Unsatisfiable,
+}
+
+pub struct Media {
+    file: File,
+    len: u64,
+    content_type: ContentType,
+    range: Span,
+}
+
+impl Media {
+    pub async fn open(path: &Path, mime: &str, range: &RangeHeader<'_>) -> Result<Media, Status> {
+        let mut file = File::open(path).await.map_err(|_| Status::NotFound)?;
+        let meta = file
+            .metadata()
+            .await
+            .map_err(|_| Status::InternalServerError)?;
+        if !meta.is_file() {
+            return Err(Status::NotFound);
+        }
+        let len = meta.len();
+        let range = range.resolve(len);
+        if let Span::Part(start, _) = range {
+            file.seek(SeekFrom::Start(start))
+                .await
+                .map_err(|_| Status::InternalServerError)?;
+        }
+        Ok(Media {
+            file,
+            len,
+            content_type: ContentType::parse_flexible(mime).unwrap_or(ContentType::Binary),
+            range,
+        })
+    }
+}
+
+impl<'r> Responder<'r, 'static> for Media {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Response::build();
+        response
+            .header(self.content_type)
+            .header(Header::new("Accept-Ranges", "bytes"));
+        match self.range {
+            // RFC 9110 §15.5.17: the current length goes in the Content-Range.
+            Span::Unsatisfiable => {
+                let problem = Problem::from(Status::RangeNotSatisfiable).respond_to(req)?;
+                response.merge(problem).header(Header::new(
+                    "Content-Range",
+                    format!("bytes */{}", self.len),
+                ));
+            }
+            Span::Part(start, end) => {
+                let window = Window {
+                    file: self.file,
+                    start,
+                    len: end - start + 1,
+                    pos: 0,
+                };
+                response
+                    .status(Status::PartialContent)
+                    .header(Header::new(
+                        "Content-Range",
+                        format!("bytes {start}-{end}/{}", self.len),
+                    ))
+                    .sized_body(window.len as usize, window);
+            }
+            Span::Full => {
+                response.sized_body(self.len as usize, self.file);
+            }
+        }
+        response.ok()
+    }
+}
+
+// A byte window over a file, so a range can be served with an exact Content-Length.
+// The file must already be positioned at `start`.
+struct Window {
+    file: File,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl AsyncRead for Window {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let remaining = (this.len - this.pos).min(buf.remaining() as u64) as usize;
+        let mut limited = ReadBuf::new(buf.initialize_unfilled_to(remaining));
+        ready!(Pin::new(&mut this.file).poll_read(cx, &mut limited))?;
+        let n = limited.filled().len();
+        buf.advance(n);
+        this.pos += n as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncSeek for Window {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let pos = match position {
+            SeekFrom::Start(n) => n as i64,
+            SeekFrom::End(n) => self.len as i64 + n,
+            SeekFrom::Current(n) => self.pos as i64 + n,
+        };
+        if pos < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before start",
+            ));
+        }
+        let target = self.start + (pos as u64).min(self.len);
+        Pin::new(&mut self.file).start_seek(SeekFrom::Start(target))
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let abs = ready!(Pin::new(&mut self.file).poll_complete(cx))?;
+        self.pos = abs - self.start;
+        Poll::Ready(Ok(self.pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(header: &str, len: u64) -> Span {
+        RangeHeader(Some(header)).resolve(len)
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(RangeHeader(None).resolve(100), Span::Full);
+        assert_eq!(resolve("bytes=0-9", 100), Span::Part(0, 9));
+        assert_eq!(resolve("bytes=90-200", 100), Span::Part(90, 99));
+        assert_eq!(resolve("bytes=10-", 100), Span::Part(10, 99));
+        assert_eq!(resolve("bytes=-10", 100), Span::Part(90, 99));
+        assert_eq!(resolve("bytes=-200", 100), Span::Part(0, 99));
+        assert_eq!(resolve("bytes=100-", 100), Span::Unsatisfiable);
+        assert_eq!(resolve("bytes=0-", 0), Span::Unsatisfiable);
+        assert_eq!(resolve("bytes=0-1,5-6", 100), Span::Full);
+        assert_eq!(resolve("bytes=9-0", 100), Span::Full);
+        assert_eq!(resolve("bytes=-0", 100), Span::Full);
+        assert_eq!(resolve("items=0-9", 100), Span::Full);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::config::ArchiveConfig;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Data, Request, Response};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Labels {
+    route: String,
+    kind: String,
+    method: &'static str,
+    status: u16,
+}
+
+#[derive(Default)]
+struct Series {
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<Labels, Series>>,
+}
+
+impl Metrics {
+    fn observe(&self, labels: Labels, seconds: f64) {
+        let mut requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+        let series = requests.entry(labels).or_default();
+        for (bucket, le) in series.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= le {
+                *bucket += 1;
+            }
+        }
+        series.count += 1;
+        series.sum += seconds;
+    }
+
+    pub fn render(&self, out: &mut String) {
+        let requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+        out.push_str("# HELP kgg_http_requests_total HTTP requests by route, kind and status.\n");
+        out.push_str("# TYPE kgg_http_requests_total counter\n");
+        for (labels, series) in requests.iter() {
+            let _ = writeln!(
+                out,
+                "kgg_http_requests_total{{{}}} {}",
+                labels.render(),
+                series.count
+            );
+        }
+        out.push_str("# HELP kgg_http_request_duration_seconds HTTP request latency.\n");
+        out.push_str("# TYPE kgg_http_request_duration_seconds histogram\n");
+        for (labels, series) in requests.iter() {
+            let labels = labels.render();
+            for (count, le) in series.buckets.iter().zip(BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "kgg_http_request_duration_seconds_bucket{{{labels},le=\"{le}\"}} {count}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "kgg_http_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}\n\
+                 kgg_http_request_duration_seconds_sum{{{labels}}} {}\n\
+                 kgg_http_request_duration_seconds_count{{{labels}}} {}",
+                series.count, series.sum, series.count
+            );
+        }
+    }
+}
+
+impl Labels {
+    fn render(&self) -> String {
+        format!(
+            "route=\"{}\",kind=\"{}\",method=\"{}\",status=\"{}\"",
+            escape(&self.route),
+            escape(&self.kind),
+            self.method,
+            self.status
+        )
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+pub fn gauge<'a>(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    values: impl IntoIterator<Item = (&'a str, f64)>,
+) {
+    let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} gauge");
+    for (kind, value) in values {
+        let _ = writeln!(out, "{name}{{kind=\"{}\"}} {value}", escape(kind));
+    }
+}
+
+struct Started(Instant);
+
+pub struct RequestMetrics;
+
+#[rocket::async_trait]
+impl Fairing for RequestMetrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        req.local_cache(|| Started(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let Some(metrics) = req.rocket().state::<Metrics>() else {
+            return;
+        };
+        let elapsed = req.local_cache(|| Started(Instant::now())).0.elapsed();
+        let route = req.route();
+        // Only configured kinds become label values, so unknown paths cannot
+        // inflate the number of series.
+        let kind = route
+            .filter(|r| r.uri.path().starts_with("/api/<kind>"))
+            .and_then(|_| req.routed_segment(0))
+            .filter(|kind| {
+                req.rocket()
+                    .state::<ArchiveConfig>()
+                    .is_some_and(|c| c.contains(kind))
+            })
+            .unwrap_or_default();
+        let labels = Labels {
+            route: route
+                .and_then(|r| r.name.as_deref())
+                .unwrap_or_default()
+                .to_owned(),
+            kind: kind.to_owned(),
+            method: req.method().as_str(),
+            status: res.status().code,
+        };
+        metrics.observe(labels, elapsed.as_secs_f64());
+    }
+}
@@ -0,0 +1,254 @@
+use crate::activity::Activity;
+use crate::config::{ArchiveConfig, KindInfo};
+use crate::fulltext::Hit;
+use crate::problem::Problem;
+use crate::songs::SongSummary;
+use crate::store::IndexStats;
+use crate::{ChatHit, ChatPage, Entry, EntryView, Failure, Page, TranscriptHit};
+use rocket::fairing::AdHoc;
+use rocket::fs::NamedFile;
+use rocket::http::{ContentType, Method};
+use rocket::response::content::RawHtml;
+use rocket::serde::json::Json;
+use rocket::{Route, State, get};
+use schemars::generate::SchemaSettings;
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use serde_json::{Map, Value, json};
+
+pub struct OpenApi(Value);
+
+// Routes are all mounted by the time ignite fairings run, so the document is built
+// once from the router itself and cannot drift from what is actually served.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("OpenAPI", |rocket| async {
+        let document = document(rocket.routes());
+        rocket.manage(OpenApi(document))
+    })
+}
+
+#[get("/openapi.json")]
+pub fn openapi_json(spec: &State<OpenApi>) -> Json<&Value> {
+    Json(&spec.0)
+}
+
+#[get("/docs")]
+pub fn docs() -> RawHtml<&'static str> {
+    RawHtml(include_str!("docs.html"))
+}
+
+// The page loads Redoc from here rather than from a CDN, so the docs never run
+// third-party code. `check` makes sure a bundle is configured when docs are on.
+#[get("/docs/redoc.standalone.js")]
+pub async fn redoc(config: &State<ArchiveConfig>) -> Option<(ContentType, NamedFile)> {
+    let file = NamedFile::open(config.redoc.as_ref()?).await.ok()?;
+    Some((ContentType::JavaScript, file))
+}
+
+// Durations are read as seconds or humantime strings and written as `1h2m3s`.
+pub fn duration_schema(_: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+        "oneOf": [
+            { "type": "string", "example": "1h2m3s" },
+            { "type": "number", "description": "seconds" },
+        ],
+        "description": "Duration; responses always use the `1h2m3s` form",
+    })
+}
+
+fn response_schema(name: &str, generator: &mut SchemaGenerator) -> Option<Schema> {
+    Some(match name {
+        "index" => generator.subschema_for::<Vec<KindInfo>>(),
+        "lists" => generator.subschema_for::<Page>(),
+        "entry" => generator.subschema_for::<EntryView>(),
+        "children" => generator.subschema_for::<Vec<Entry>>(),
+        "create_entry" | "replace_entry" | "update_entry" => generator.subschema_for::<Entry>(),
+        "search" => generator.subschema_for::<Vec<Hit>>(),
+        "chat_search" => generator.subschema_for::<Vec<ChatHit>>(),
+        "transcript_search" => generator.subschema_for::<Vec<TranscriptHit>>(),
+        "song_index" => generator.subschema_for::<Vec<SongSummary>>(),
+        "status" => generator.subschema_for::<IndexStats>(),
+        "failures" => generator.subschema_for::<Vec<Failure>>(),
+        "entry_chat" => generator.subschema_for::<ChatPage>(),
+        "chat_activity" => generator.subschema_for::<Activity>(),
+        _ => return None,
+    })
+}
+
+// Routes that answer with something other than JSON; the value is unused there.
+fn content_types(name: &str) -> &'static [&'static str] {
+    match name {
+        "rss_feed" | "podcast_feed" => &["application/rss+xml"],
+        "atom_feed" => &["application/atom+xml"],
+        "json_feed" => &["application/feed+json"],
+        "entry_media" => &["video/*", "audio/*"],
+        "sidecar" => &["*/*"],
+        "entry_chapters" => &[
+            "text/vtt",
+            "application/x-subrip",
+            "application/json+chapters",
+            "text/plain",
+        ],
+        "scrape" => &["text/plain"],
+        "docs" => &["text/html"],
+        "redoc" => &["text/javascript"],
+        _ => &["application/json"],
+    }
+}
+
+// Guarded routes require the bearer token; everything else except the public
+// service routes accepts it to reveal private entries and fields.
+fn security(name: &str) -> Option<Value> {
+    match name {
+        "failures" | "create_entry" | "replace_entry" | "update_entry" | "delete_entry" => {
+            Some(json!([{ "bearer": [] }]))
+        }
+        "index" | "status" | "openapi_json" | "docs" | "redoc" | "scrape" => None,
+        _ => Some(json!([{}, { "bearer": [] }])),
+    }
+}
+
+// Rocket routes carry no parameter types, so query parameters are described here:
+// whether the route requires them, and their schema.
+fn query_param(name: &str, param: &str) -> (bool, Value) {
+    let duration = json!({ "type": "string", "example": "1h2m3s" });
+    match param {
+        "q" => (
+            matches!(name, "search" | "transcript_search"),
+            json!({ "type": "string", "minLength": 1 }),
+        ),
+        "limit" => (
+            false,
+            json!({ "type": "integer", "minimum": 1, "description": "clamped to 500" }),
+        ),
+        "threshold" => (
+            false,
+            json!({ "type": "number", "description": "standard deviations above the mean" }),
+        ),
+        "after" | "before" => (
+            false,
+            json!({ "type": "string", "description": "RFC 3339 date-time or YYYY-MM-DD" }),
+        ),
+        "from" | "to" | "interval" | "min_duration" | "max_duration" => (false, duration),
+        "format" if name == "sidecar" => {
+            (false, json!({ "type": "string", "enum": ["srt", "vtt"] }))
+        }
+        "format" if name == "entry_chapters" => (
+            false,
+            json!({ "type": "string", "enum": ["vtt", "srt", "json", "ffmetadata"] }),
+        ),
+        _ => (false, json!({ "type": "string" })),
+    }
+}
+
+fn schema_of<T: JsonSchema>(generator: &mut SchemaGenerator) -> Value {
+    generator.subschema_for::<T>().to_value()
+}
+
+// `/api/<kind>/<id>` → (`/api/{kind}/{id}`, [kind, id])
+fn path_template(path: &str) -> (String, Vec<String>) {
+    let mut params = Vec::new();
+    let segments: Vec<_> = path
+        .split('/')
+        .map(
+            |segment| match segment.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+                Some(name) => {
+                    let name = name.trim_end_matches("..");
+                    params.push(name.to_owned());
+                    format!("{{{name}}}")
+                }
+                None => segment.to_owned(),
+            },
+        )
+        .collect();
+    (segments.join("/"), params)
+}
+
+fn query_params(query: Option<&str>) -> Vec<String> {
+    query
+        .into_iter()
+        .flat_map(|q| q.split('&'))
+        .filter_map(|p| p.strip_prefix('<')?.strip_suffix('>'))
+        .map(|p| p.trim_end_matches("..").to_owned())
+        .collect()
+}
+
+pub fn document<'a>(routes: impl Iterator<Item = &'a Route>) -> Value {
+    let mut generator = SchemaSettings::openapi3().into_generator();
+    let error = schema_of::<Problem>(&mut generator);
+    let mut paths = Map::new();
+    for route in routes {
+        let (path, path_params) = path_template(route.uri.path());
+        let name = route.name.as_deref().unwrap_or_default();
+        let mut parameters: Vec<_> = path_params
+            .iter()
+            .map(|p| json!({ "name": p, "in": "path", "required": true, "schema": { "type": "string" } }))
+            .collect();
+        parameters.extend(query_params(route.uri.query()).into_iter().map(|p| {
+            let (required, schema) = query_param(name, &p);
+            json!({ "name": p, "in": "query", "required": required, "schema": schema })
+        }));
+        let body = response_schema(name, &mut generator).map_or(json!({}), Schema::to_value);
+        let content: Map<_, _> = content_types(name)
+            .iter()
+            .map(|&mime| {
+                let schema = match mime {
+                    "application/json" => body.clone(),
+                    "video/*" | "audio/*" | "*/*" => {
+                        json!({ "type": "string", "format": "binary" })
+                    }
+                    _ => json!({ "type": "string" }),
+                };
+                (mime.to_owned(), json!({ "schema": schema }))
+            })
+            .collect();
+        let success = match name {
+            "create_entry" => json!({ "201": { "description": "Created", "content": content } }),
+            "delete_entry" => json!({ "204": { "description": "Deleted" } }),
+            "entry_media" | "sidecar" => json!({
+                "200": { "description": "Success", "content": content },
+                "206": { "description": "Requested byte range", "content": content },
+            }),
+            _ => json!({ "200": { "description": "Success", "content": content } }),
+        };
+        let mut responses = success;
+        responses["default"] = json!({
+            "description": "Error",
+            "content": { "application/problem+json": { "schema": error } },
+        });
+        let mut operation = json!({
+            "operationId": name,
+            "parameters": parameters,
+            "responses": responses,
+        });
+        if let Some(security) = security(name) {
+            operation["security"] = security;
+        }
+        if matches!(route.method, Method::Post | Method::Put | Method::Patch) {
+            let schema = match route.method {
+                Method::Patch => {
+                    json!({ "type": "object", "description": "JSON Merge Patch (RFC 7396)" })
+                }
+                _ => schema_of::<Entry>(&mut generator),
+            };
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": { "application/json": { "schema": schema } },
+            });
+        }
+        let item = paths
+            .entry(path)
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .expect("path items are objects");
+        item.insert(route.method.as_str().to_ascii_lowercase(), operation);
+    }
+    json!({
+        "openapi": "3.0.3",
+        "info": { "title": "kgg", "version": env!("CARGO_PKG_VERSION") },
+        "paths": paths,
+        "components": {
+            "schemas": generator.take_definitions(true),
+            "securitySchemes": { "bearer": { "type": "http", "scheme": "bearer" } },
+        },
+    })
+}
@@ -0,0 +1,99 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::Serialize;
+use schemars::JsonSchema;
+use std::io::Cursor;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// An RFC 7807 problem. Routes build one with a specific `type` and detail; bare
+// statuses (from `?` on a `Status` or from the catcher) fall back to `about:blank`.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub title: String,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub request_id: String,
+}
+
+impl Problem {
+    pub fn new(status: Status, kind: &str, title: &str, detail: impl Into<String>) -> Problem {
+        Problem {
+            kind: format!("urn:kgg:problem:{kind}"),
+            title: title.to_owned(),
+            status: status.code,
+            detail: Some(detail.into()),
+            request_id: String::new(),
+        }
+    }
+}
+
+impl From<Status> for Problem {
+    fn from(status: Status) -> Problem {
+        Problem {
+            kind: "about:blank".to_owned(),
+            title: status.reason_lossy().to_owned(),
+            status: status.code,
+            detail: None,
+            request_id: String::new(),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for Problem {
+    fn respond_to(mut self, req: &'r Request<'_>) -> response::Result<'static> {
+        self.request_id = RequestId::of(req).to_owned();
+        let body = serde_json::to_vec(&self).map_err(|_| Status::InternalServerError)?;
+        Response::build()
+            .status(Status::new(self.status))
+            .header(ContentType::new("application", "problem+json"))
+            .sized_body(body.len(), Cursor::new(body))
+            .ok()
+    }
+}
+
+pub struct RequestId(String);
+
+impl RequestId {
+    pub fn of<'r>(req: &'r Request<'_>) -> &'r str {
+        &req.local_cache(|| {
+            static NEXT: AtomicU64 = AtomicU64::new(0);
+            // Prefixing the process start keeps ids unique across restarts.
+            static START: OnceLock<u64> = OnceLock::new();
+            let start = START.get_or_init(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs())
+            });
+            RequestId(format!(
+                "{start:x}-{:x}",
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ))
+        })
+        .0
+    }
+}
+
+// Echoes the id on every response so that logs and client reports can be matched.
+pub struct RequestIds;
+
+#[rocket::async_trait]
+impl Fairing for RequestIds {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request id",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        res.set_header(Header::new("X-Request-Id", RequestId::of(req).to_owned()));
+    }
+}
@@ -0,0 +1,116 @@
+use crate::{Entry, parse_duration_flex, serialize_duration};
+use chrono::{DateTime, Utc};
+use rocket::serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Song {
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(
+        deserialize_with = "parse_duration_flex",
+        serialize_with = "serialize_duration"
+    )]
+    #[schemars(schema_with = "crate::openapi::duration_schema")]
+    pub offset: Duration,
+}
+
+pub fn check(setlist: &[Song], duration: Duration) -> Result<(), String> {
+    for song in setlist {
+        if song.title.trim().is_empty() {
+            return Err("song title must not be empty".into());
+        }
+        if !duration.is_zero() && song.offset >= duration {
+            return Err(format!("song {:?} starts after the end", song.title));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct SongSummary {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    pub count: usize,
+    pub first_performed: DateTime<Utc>,
+    pub last_performed: DateTime<Utc>,
+    pub performances: Vec<Performance>,
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Performance {
+    pub kind: String,
+    pub id: String,
+    pub entry_title: String,
+    pub performed_at: DateTime<Utc>,
+    #[serde(serialize_with = "serialize_duration")]
+    #[schemars(schema_with = "crate::openapi::duration_schema")]
+    pub offset: Duration,
+}
+
+fn key(song: &Song) -> (String, String) {
+    let normalize = |s: &str| {
+        s.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    };
+    (
+        normalize(&song.title),
+        normalize(song.artist.as_deref().unwrap_or_default()),
+    )
+}
+
+// Groups setlist items by title and artist (ignoring case and spacing), oldest
+// performance first; the spelling of the earliest performance is the one reported.
+pub fn summarize<'a>(
+    entries: impl IntoIterator<Item = (&'a str, &'a Entry)>,
+    matches: impl Fn(&Song) -> bool,
+) -> Vec<SongSummary> {
+    let mut songs: HashMap<(String, String), SongSummary> = HashMap::new();
+    for (kind, entry) in entries {
+        for song in entry.setlist.iter().filter(|s| matches(s)) {
+            let performance = Performance {
+                kind: kind.to_owned(),
+                id: entry.id.clone(),
+                entry_title: entry.title.clone(),
+                performed_at: entry.created_at,
+                offset: song.offset,
+            };
+            let summary = songs.entry(key(song)).or_insert_with(|| SongSummary {
+                title: String::new(),
+                artist: None,
+                count: 0,
+                first_performed: entry.created_at,
+                last_performed: entry.created_at,
+                performances: Vec::new(),
+            });
+            if summary.title.is_empty() || entry.created_at < summary.first_performed {
+                summary.title = song.title.trim().to_owned();
+                summary.artist = song.artist.as_ref().map(|a| a.trim().to_owned());
+                summary.first_performed = entry.created_at;
+            }
+            summary.performances.push(performance);
+        }
+    }
+    let mut songs: Vec<_> = songs
+        .into_values()
+        .map(|mut song| {
+            song.performances
+                .sort_by_key(|p| (p.performed_at, p.offset));
+            song.count = song.performances.len();
+            song.first_performed = song.performances[0].performed_at;
+            song.last_performed = song.performances[song.count - 1].performed_at;
+            song
+        })
+        .collect();
+    songs.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.title.cmp(&b.title)));
+    songs
+}
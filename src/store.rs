@@ -0,0 +1,517 @@
+use crate::config::ArchiveConfig;
+use crate::etag::etag;
+use crate::media::Sidecar;
+use crate::{Entry, LoadError, get_entries, get_entry, is_entry_file, sidecar_name};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use notify::{
+    Config, Event, EventHandler, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher,
+    recommended_watcher,
+};
+use rocket::serde::Serialize;
+use schemars::JsonSchema;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, RwLock, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::Builder;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+pub struct Index {
+    inner: Arc<Inner>,
+    writes: Mutex<()>,
+    _watchers: Option<Arc<Mutex<Watchers>>>,
+}
+
+struct Inner {
+    kinds: RwLock<HashMap<String, Kind>>,
+    stats: RwLock<IndexStats>,
+}
+
+struct Kind {
+    dir: PathBuf,
+    parent: Option<String>,
+    files: HashMap<String, Entry>,
+    failures: HashMap<String, LoadError>,
+    sidecars: HashMap<String, Vec<Sidecar>>,
+    // Built on the first listing after a change, outside the `kinds` write lock,
+    // so bursts of watcher events (a live chat log growing) do not re-sort each time.
+    sorted: OnceLock<Arc<[Entry]>>,
+    scan: Duration,
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct IndexStats {
+    pub entries: usize,
+    pub malformed: usize,
+    pub rebuild_ms: f64,
+    pub rebuilt_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub watcher: &'static str,
+}
+
+impl Index {
+    pub fn build(config: &ArchiveConfig) -> Index {
+        let mut index = Index::load(config);
+        index._watchers = Some(Watchers::start(&index.inner, config.poll));
+        index
+    }
+
+    pub fn load(config: &ArchiveConfig) -> Index {
+        let start = Instant::now();
+        let kinds: HashMap<_, _> = config
+            .kinds
+            .iter()
+            .filter_map(|(kind, c)| Some((kind, c, config.dir(kind)?)))
+            .map(|(kind, c, dir)| {
+                let dir = dir.canonicalize().unwrap_or(dir);
+                (kind.clone(), Kind::new(dir, c.parent.clone()))
+            })
+            .collect();
+        let now = Utc::now();
+        let stats = IndexStats {
+            entries: kinds.values().map(|k| k.files.len()).sum(),
+            malformed: kinds.values().map(|k| k.failures.len()).sum(),
+            rebuild_ms: start.elapsed().as_secs_f64() * 1000.0,
+            rebuilt_at: now,
+            updated_at: now,
+            watcher: "none",
+        };
+        info!(
+            "indexed {} entries in {:.1}ms",
+            stats.entries, stats.rebuild_ms
+        );
+        let inner = Arc::new(Inner {
+            kinds: RwLock::new(kinds),
+            stats: RwLock::new(stats),
+        });
+        Index {
+            inner,
+            writes: Mutex::new(()),
+            _watchers: None,
+        }
+    }
+
+    pub fn list(&self, kind: &str) -> Option<Arc<[Entry]>> {
+        let kinds = self.inner.kinds.read().unwrap();
+        kinds.get(kind).map(Kind::sorted)
+    }
+
+    pub fn get(&self, kind: &str, id: &str) -> Option<Entry> {
+        let kinds = self.inner.kinds.read().unwrap();
+        kinds.get(kind)?.files.get(id).cloned()
+    }
+
+    pub fn failures(&self) -> Vec<(String, LoadError)> {
+        let kinds = self.inner.kinds.read().unwrap();
+        let mut failures: Vec<_> = kinds
+            .iter()
+            .flat_map(|(kind, k)| k.failures.values().map(|f| (kind.clone(), f.clone())))
+            .collect();
+        failures.sort_by(|a, b| a.1.path.cmp(&b.1.path));
+        failures
+    }
+
+    pub fn failure(&self, kind: &str, stem: &str) -> Option<LoadError> {
+        let kinds = self.inner.kinds.read().unwrap();
+        kinds.get(kind)?.failures.get(stem).cloned()
+    }
+
+    pub fn dangling(&self) -> Vec<(String, LoadError)> {
+        let kinds = self.inner.kinds.read().unwrap();
+        let mut dangling = Vec::new();
+        for (name, kind) in kinds.iter() {
+            let Some(parent_kind) = &kind.parent else {
+                continue;
+            };
+            for entry in kind.files.values() {
+                let (Some(vod_id), Some(end)) = (&entry.vod_id, entry.end_offset) else {
+                    continue;
+                };
+                let parent = kinds.get(parent_kind).and_then(|k| k.files.get(vod_id));
+                let error = match parent {
+                    None => format!("vod_id references missing {parent_kind}/{vod_id}"),
+                    Some(parent) if end > parent.duration => {
+                        format!("end_offset is past the end of {parent_kind}/{vod_id}")
+                    }
+                    Some(_) => continue,
+                };
+                let path = kind.dir.join(&entry.id).with_extension("json");
+                dangling.push((name.clone(), LoadError::schema(&path, error)));
+            }
+        }
+        dangling.sort_by(|a, b| a.1.path.cmp(&b.1.path));
+        dangling
+    }
+
+    // How long the last full directory scan of each kind took.
+    pub fn scan_durations(&self) -> Vec<(String, Duration)> {
+        let kinds = self.inner.kinds.read().unwrap();
+        kinds
+            .iter()
+            .map(|(name, k)| (name.clone(), k.scan))
+            .collect()
+    }
+
+    pub fn stats(&self) -> IndexStats {
+        self.inner.stats.read().unwrap().clone()
+    }
+
+    pub fn path(&self, kind: &str, id: &str) -> Option<PathBuf> {
+        let kinds = self.inner.kinds.read().unwrap();
+        Some(kinds.get(kind)?.dir.join(id).with_extension("json"))
+    }
+
+    pub fn sidecar_path(&self, kind: &str, id: &str, suffix: &str) -> Option<PathBuf> {
+        let kinds = self.inner.kinds.read().unwrap();
+        Some(kinds.get(kind)?.dir.join(format!("{id}.{suffix}")))
+    }
+
+    // Held across a read-check-write sequence so that `If-Match` checks cannot race.
+    pub fn lock_writes(&self) -> MutexGuard<'_, ()> {
+        self.writes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn current_version(&self, kind: &str, id: &str) -> io::Result<String> {
+        let path = self.path(kind, id).ok_or(io::ErrorKind::NotFound)?;
+        Ok(etag(&fs::read(path)?))
+    }
+
+    pub fn read_raw(&self, kind: &str, id: &str) -> io::Result<(Value, String)> {
+        let path = self.path(kind, id).ok_or(io::ErrorKind::NotFound)?;
+        let bytes = fs::read(path)?;
+        Ok((serde_json::from_slice(&bytes)?, etag(&bytes)))
+    }
+
+    // Entries are written to a temporary file in the same directory and renamed into
+    // place, so readers and the watcher never observe a partially written file.
+    pub fn write(&self, kind: &str, id: &str, value: &Value, create: bool) -> io::Result<String> {
+        let path = self.path(kind, id).ok_or(io::ErrorKind::NotFound)?;
+        let mut bytes = serde_json::to_vec_pretty(value)?;
+        bytes.push(b'\n');
+        // Temporary files are private by default; new entries get the mode a plain
+        // create would (0666 less the umask) and replaced ones keep their own.
+        let mut builder = Builder::new();
+        #[cfg(unix)]
+        builder.permissions(fs::Permissions::from_mode(0o666));
+        let mut tmp = builder.tempfile_in(path.parent().unwrap_or(Path::new(".")))?;
+        if let Ok(meta) = fs::metadata(&path) {
+            tmp.as_file().set_permissions(meta.permissions())?;
+        }
+        tmp.write_all(&bytes)?;
+        tmp.as_file().sync_all()?;
+        if create {
+            tmp.persist_noclobber(&path)?;
+        } else {
+            tmp.persist(&path)?;
+        }
+        self.inner.refresh(&path);
+        Ok(etag(&bytes))
+    }
+
+    pub fn delete(&self, kind: &str, id: &str) -> io::Result<()> {
+        let path = self.path(kind, id).ok_or(io::ErrorKind::NotFound)?;
+        fs::remove_file(&path)?;
+        self.inner.refresh(&path);
+        Ok(())
+    }
+}
+
+impl Inner {
+    // Rebuilds a kind whose directory could not be read, typically because it did
+    // not exist yet.
+    fn rescan(&self, dir: &Path, canonical: PathBuf) {
+        let Some(parent) = self
+            .kinds
+            .read()
+            .unwrap()
+            .values()
+            .find(|k| k.dir == dir)
+            .map(|k| k.parent.clone())
+        else {
+            return;
+        };
+        let rebuilt = Kind::new(canonical, parent);
+        let mut kinds = self.kinds.write().unwrap();
+        if let Some(kind) = kinds.values_mut().find(|k| k.dir == dir) {
+            *kind = rebuilt;
+        }
+        self.update_stats(&kinds);
+    }
+
+    fn update_stats(&self, kinds: &HashMap<String, Kind>) {
+        let mut stats = self.stats.write().unwrap();
+        stats.entries = kinds.values().map(|k| k.files.len()).sum();
+        stats.malformed = kinds.values().map(|k| k.failures.len()).sum();
+        stats.updated_at = Utc::now();
+    }
+
+    fn refresh(&self, path: &Path) {
+        let Some(dir) = path.parent() else {
+            return;
+        };
+        let mut kinds = self.kinds.write().unwrap();
+        let Some(kind) = kinds.values_mut().find(|k| k.dir == dir) else {
+            return;
+        };
+        if let Some((stem, suffix)) = sidecar_name(path) {
+            kind.refresh_sidecar(path, stem, suffix);
+            return;
+        }
+        let Some(stem) = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .filter(|_| is_entry_file(path))
+        else {
+            return;
+        };
+        kind.files.remove(stem);
+        kind.failures.remove(stem);
+        if path.exists() {
+            let loaded = get_entry(path).and_then(|e| kind.check(path, e));
+            kind.insert(stem.to_owned(), loaded);
+        }
+        kind.changed();
+        self.update_stats(&kinds);
+    }
+}
+
+// Directories are watched natively where possible and polled where that fails
+// (inotify watch limits, unsupported filesystems) or when polling is configured.
+// Directories that do not exist yet are retried until they appear.
+struct Watchers {
+    events: Events,
+    native: Option<RecommendedWatcher>,
+    poll: Option<PollWatcher>,
+    pending: Vec<PathBuf>,
+    native_dirs: usize,
+    poll_dirs: usize,
+}
+
+#[derive(Clone)]
+struct Events(Arc<Inner>);
+
+impl EventHandler for Events {
+    fn handle_event(&mut self, res: notify::Result<Event>) {
+        match res {
+            // Reading a file raises access events; refreshing on those would loop forever.
+            Ok(event) if event.kind.is_access() => {}
+            Ok(event) => event.paths.iter().for_each(|p| self.0.refresh(p)),
+            Err(e) => warn!("index watcher error: {e}"),
+        }
+    }
+}
+
+impl Watchers {
+    fn start(inner: &Arc<Inner>, poll: bool) -> Arc<Mutex<Watchers>> {
+        let events = Events(inner.clone());
+        let native = match poll {
+            true => None,
+            false => recommended_watcher(events.clone())
+                .inspect_err(|e| warn!("native file watching unavailable ({e}), polling instead"))
+                .ok(),
+        };
+        let mut watchers = Watchers {
+            events,
+            native,
+            poll: None,
+            pending: Vec::new(),
+            native_dirs: 0,
+            poll_dirs: 0,
+        };
+        let dirs: Vec<_> = inner
+            .kinds
+            .read()
+            .unwrap()
+            .values()
+            .map(|k| k.dir.clone())
+            .collect();
+        for dir in dirs {
+            if !watchers.watch(&dir) {
+                watchers.pending.push(dir);
+            }
+        }
+        watchers.report(inner);
+        let watchers = Arc::new(Mutex::new(watchers));
+        if !watchers.lock().unwrap().pending.is_empty() {
+            let (inner, weak) = (Arc::downgrade(inner), Arc::downgrade(&watchers));
+            thread::spawn(move || Watchers::retry(inner, weak));
+        }
+        watchers
+    }
+
+    fn watch(&mut self, dir: &Path) -> bool {
+        if !dir.is_dir() {
+            return false;
+        }
+        if let Some(native) = &mut self.native {
+            match native.watch(dir, RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    self.native_dirs += 1;
+                    return true;
+                }
+                Err(e) => warn!("cannot watch {} ({e}), polling instead", dir.display()),
+            }
+        }
+        let poll = match &mut self.poll {
+            Some(poll) => poll,
+            None => {
+                let config = Config::default().with_poll_interval(POLL_INTERVAL);
+                match PollWatcher::new(self.events.clone(), config) {
+                    Ok(poll) => self.poll.insert(poll),
+                    Err(e) => {
+                        warn!("cannot poll {}: {e}", dir.display());
+                        return false;
+                    }
+                }
+            }
+        };
+        match poll.watch(dir, RecursiveMode::NonRecursive) {
+            Ok(()) => {
+                self.poll_dirs += 1;
+                true
+            }
+            Err(e) => {
+                warn!("cannot poll {}: {e}", dir.display());
+                false
+            }
+        }
+    }
+
+    fn report(&self, inner: &Inner) {
+        inner.stats.write().unwrap().watcher = match (self.native_dirs, self.poll_dirs) {
+            (0, 0) => "none",
+            (_, 0) => "native",
+            (0, _) => "poll",
+            _ => "mixed",
+        };
+    }
+
+    // Runs until every directory is watched or the index is dropped.
+    fn retry(inner: Weak<Inner>, watchers: Weak<Mutex<Watchers>>) {
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let (Some(inner), Some(watchers)) = (inner.upgrade(), watchers.upgrade()) else {
+                return;
+            };
+            let mut watchers = watchers.lock().unwrap_or_else(|e| e.into_inner());
+            for dir in std::mem::take(&mut watchers.pending) {
+                // Watch first so that files created during the rescan are not missed.
+                match dir.canonicalize() {
+                    Ok(canonical) if watchers.watch(&canonical) => {
+                        info!("indexing {}", canonical.display());
+                        inner.rescan(&dir, canonical);
+                    }
+                    _ => watchers.pending.push(dir),
+                }
+            }
+            watchers.report(&inner);
+            if watchers.pending.is_empty() {
+                return;
+            }
+        }
+    }
+}
+
+impl Kind {
+    fn new(dir: PathBuf, parent: Option<String>) -> Kind {
+        let mut kind = Kind {
+            dir,
+            parent,
+            files: HashMap::new(),
+            failures: HashMap::new(),
+            sidecars: HashMap::new(),
+            sorted: OnceLock::new(),
+            scan: Duration::ZERO,
+        };
+        for path in fs::read_dir(&kind.dir).into_iter().flatten().flatten() {
+            let path = path.path();
+            if let Some((stem, suffix)) = sidecar_name(&path)
+                && let Some(sidecar) = Sidecar::stat(&path, suffix)
+            {
+                kind.sidecars
+                    .entry(stem.to_owned())
+                    .or_default()
+                    .push(sidecar);
+            }
+        }
+        kind.sidecars
+            .values_mut()
+            .for_each(|s| s.sort_by(|a, b| a.suffix.cmp(&b.suffix)));
+        let start = Instant::now();
+        let loaded = get_entries(&kind.dir);
+        kind.scan = start.elapsed();
+        match loaded {
+            Ok(loaded) => {
+                for (stem, result) in loaded {
+                    let path = kind.dir.join(&stem).with_extension("json");
+                    let result = result.and_then(|e| kind.check(&path, e));
+                    kind.insert(stem, result);
+                }
+            }
+            Err(e) => {
+                warn!("cannot read kind directory {}: {e}", kind.dir.display());
+                // "." can never be an entry stem, so it cannot collide with a file.
+                let error = LoadError::io(&kind.dir, e);
+                kind.failures.insert(".".to_owned(), error);
+            }
+        }
+        kind
+    }
+
+    fn check(&self, path: &Path, entry: Entry) -> Result<Entry, LoadError> {
+        entry
+            .check_schema(self.parent.is_some())
+            .map_err(|e| LoadError::schema(path, e))
+            .inspect_err(|e| warn!("skipping {e}"))?;
+        Ok(entry)
+    }
+
+    fn refresh_sidecar(&mut self, path: &Path, stem: &str, suffix: &str) {
+        let sidecars = self.sidecars.entry(stem.to_owned()).or_default();
+        sidecars.retain(|s| s.suffix != suffix);
+        if let Some(sidecar) = Sidecar::stat(path, suffix) {
+            sidecars.push(sidecar);
+            sidecars.sort_by(|a, b| a.suffix.cmp(&b.suffix));
+        }
+        let sidecars = sidecars.clone();
+        if let Some(entry) = self.files.get_mut(stem) {
+            entry.sidecars = sidecars;
+            self.changed();
+        }
+    }
+
+    fn insert(&mut self, stem: String, loaded: Result<Entry, LoadError>) {
+        match loaded {
+            Ok(mut entry) => {
+                entry.sidecars = self.sidecars.get(&stem).cloned().unwrap_or_default();
+                self.files.insert(stem, entry);
+            }
+            Err(e) => {
+                self.failures.insert(stem, e);
+            }
+        }
+    }
+
+    fn changed(&mut self) {
+        self.sorted.take();
+    }
+
+    fn sorted(&self) -> Arc<[Entry]> {
+        self.sorted
+            .get_or_init(|| {
+                let mut entries: Vec<_> = self.files.values().cloned().collect();
+                entries.sort_by(|a, b| b.sort_key().cmp(&a.sort_key()));
+                entries.into()
+            })
+            .clone()
+    }
+}
@@ -0,0 +1,174 @@
+use crate::cache::FileCache;
+use rocket::serde::Serialize;
+use std::fmt::Write;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// Parsed cues are cached up to roughly this many bytes across all subtitle files.
+const CACHE_BYTES: usize = 64 << 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Srt,
+    Vtt,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Format> {
+        match s.to_ascii_lowercase().as_str() {
+            "srt" => Some(Format::Srt),
+            "vtt" | "webvtt" => Some(Format::Vtt),
+            _ => None,
+        }
+    }
+
+    pub fn of_suffix(suffix: &str) -> Option<Format> {
+        Format::parse(suffix.rsplit('.').next()?)
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Srt => "application/x-subrip",
+            Format::Vtt => "text/vtt",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Cue {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+}
+
+impl Cue {
+    // Cue text may carry WebVTT or SRT markup (`<i>`, `<c.color>`, `<00:01.000>`).
+    pub fn plain_text(&self) -> String {
+        let mut out = String::with_capacity(self.text.len());
+        let mut in_tag = false;
+        for c in self.text.chars() {
+            match c {
+                '<' => in_tag = true,
+                '>' if in_tag => in_tag = false,
+                '\n' if !in_tag => out.push(' '),
+                c if !in_tag => out.push(c),
+                _ => {}
+            }
+        }
+        out
+    }
+}
+
+// Both formats are a sequence of blank-line separated blocks with a `start --> end`
+// timing line, so one parser covers them; blocks without timing (the WebVTT header,
+// NOTE and STYLE blocks) are skipped.
+pub fn parse(text: &str) -> Vec<Cue> {
+    let text = text.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let mut cues = Vec::new();
+    for block in text.split("\n\n") {
+        let mut lines = block.lines().skip_while(|l| !l.contains("-->"));
+        let Some(timing) = lines.next() else {
+            continue;
+        };
+        let Some((start, rest)) = timing.split_once("-->") else {
+            continue;
+        };
+        let end = rest.split_whitespace().next().unwrap_or_default();
+        let (Some(start_ms), Some(end_ms)) = (parse_timestamp(start.trim()), parse_timestamp(end))
+        else {
+            continue;
+        };
+        cues.push(Cue {
+            start_ms,
+            end_ms,
+            text: lines.collect::<Vec<_>>().join("\n"),
+        });
+    }
+    cues
+}
+
+// Accepts `hh:mm:ss,mmm`, `hh:mm:ss.mmm` and the WebVTT short form `mm:ss.mmm`.
+fn parse_timestamp(s: &str) -> Option<u64> {
+    let (clock, frac) = s.split_once([',', '.']).unwrap_or((s, "0"));
+    let mut parts = clock.rsplit(':').map(str::parse::<u64>);
+    let seconds = parts.next()?.ok()?;
+    let minutes = parts.next()?.ok()?;
+    let hours = parts.next().transpose().ok()?.unwrap_or(0);
+    if parts.next().is_some() || frac.len() > 3 {
+        return None;
+    }
+    let millis = format!("{frac:0<3}").parse::<u64>().ok()?;
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1000 + millis)
+}
+
+fn timestamp(ms: u64, separator: char) -> String {
+    let (h, m, s) = (ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60);
+    format!("{h:02}:{m:02}:{s:02}{separator}{:03}", ms % 1000)
+}
+
+pub fn render(cues: &[Cue], format: Format) -> String {
+    let mut out = String::new();
+    if format == Format::Vtt {
+        out.push_str("WEBVTT\n\n");
+    }
+    for (i, cue) in cues.iter().enumerate() {
+        let separator = match format {
+            Format::Srt => {
+                let _ = writeln!(out, "{}", i + 1);
+                ','
+            }
+            Format::Vtt => '.',
+        };
+        let _ = writeln!(
+            out,
+            "{} --> {}\n{}\n",
+            timestamp(cue.start_ms, separator),
+            timestamp(cue.end_ms, separator),
+            cue.text
+        );
+    }
+    out
+}
+
+// Parsing reads the whole file, so callers run this off the async workers.
+#[derive(Clone)]
+pub struct SubtitleStore {
+    cache: FileCache<Vec<Cue>>,
+}
+
+impl Default for SubtitleStore {
+    fn default() -> Self {
+        SubtitleStore {
+            cache: FileCache::new(CACHE_BYTES, |cues| {
+                cues.iter().map(|c| size_of::<Cue>() + c.text.len()).sum()
+            }),
+        }
+    }
+}
+
+impl SubtitleStore {
+    pub fn cues(&self, path: &Path) -> io::Result<Arc<Vec<Cue>>> {
+        self.cache.get(path, |path| {
+            Ok(parse(&String::from_utf8_lossy(&std::fs::read(path)?)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(parse_timestamp("01:02:03,456"), Some(3_723_456));
+        assert_eq!(parse_timestamp("01:02:03.4"), Some(3_723_400));
+        assert_eq!(parse_timestamp("02:03.456"), Some(123_456));
+        assert_eq!(parse_timestamp("00:00:05"), Some(5000));
+        assert_eq!(parse_timestamp("5.000"), None);
+        assert_eq!(parse_timestamp("1:2:3:4.000"), None);
+        assert_eq!(parse_timestamp("00:00:01.0001"), None);
+        assert_eq!(parse_timestamp("aa:00.000"), None);
+    }
+}